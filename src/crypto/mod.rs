@@ -0,0 +1,257 @@
+//! Cryptographic primitives, implemented by either OpenSSL (feature `openssl`, the default)
+//! or the pure-Rust RustCrypto crates (feature `rustcrypto`).
+//! If both features are enabled, OpenSSL is used.
+use std::fmt::{Debug, Display, Formatter};
+use std::str::FromStr;
+use zeroize::Zeroize;
+use crate::errors::Errors;
+use crate::secret::{SecretBytes, SecretString};
+use crate::Result;
+
+#[cfg(feature = "openssl")]
+pub(crate) mod openssl_backend;
+#[cfg(feature = "rustcrypto")]
+pub(crate) mod rustcrypto_backend;
+#[cfg(test)]
+mod tests;
+
+#[cfg(feature = "openssl")]
+pub(crate) type ActiveBackend = openssl_backend::OpenSsl;
+#[cfg(all(feature = "rustcrypto", not(feature = "openssl")))]
+pub(crate) type ActiveBackend = rustcrypto_backend::RustCrypto;
+#[cfg(not(any(feature = "openssl", feature = "rustcrypto")))]
+compile_error!("either the `openssl` or the `rustcrypto` feature has to be enabled");
+
+/// Strips separators and case, so `"HMAC-SHA-256"`, `"hmac_sha256"` and `"SHA256"` compare equal.
+fn normalize(name: &str) -> String {
+    name.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_ascii_uppercase()
+}
+
+/// Pseudo random function used by PBKDF2 to derive the key protecting the private key.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Prf {
+    HmacSha1,
+    HmacSha256,
+    HmacSha384,
+    HmacSha512,
+}
+impl FromStr for Prf {
+    type Err = Errors;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let normalized = normalize(s);
+        match normalized.strip_prefix("HMAC").unwrap_or(&normalized) {
+            "SHA1" => Ok(Prf::HmacSha1),
+            "SHA256" => Ok(Prf::HmacSha256),
+            "SHA384" => Ok(Prf::HmacSha384),
+            "SHA512" => Ok(Prf::HmacSha512),
+            _ => Err(Errors::UnsupportedAlgorithm(format!("key derivation function {s}"))),
+        }
+    }
+}
+impl Display for Prf {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Prf::HmacSha1 => "sha1",
+            Prf::HmacSha256 => "sha256",
+            Prf::HmacSha384 => "sha384",
+            Prf::HmacSha512 => "sha512",
+        })
+    }
+}
+
+/// Symmetric cipher used for private keys, chat keys, messages and files.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub enum EncryptionFunction {
+    #[default]
+    Aes256Cbc,
+}
+impl EncryptionFunction {
+    pub fn key_len(&self) -> usize {
+        match self {
+            EncryptionFunction::Aes256Cbc => 32,
+        }
+    }
+}
+impl FromStr for EncryptionFunction {
+    type Err = Errors;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match normalize(s).as_str() {
+            // plain "AES-CBC" is what WebCrypto calls it, the key length is given by the key
+            "AES256CBC" | "AESCBC256" | "AESCBC" => Ok(EncryptionFunction::Aes256Cbc),
+            _ => Err(Errors::UnsupportedAlgorithm(format!("encryption function {s}"))),
+        }
+    }
+}
+impl Display for EncryptionFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            EncryptionFunction::Aes256Cbc => "aes-256-cbc",
+        })
+    }
+}
+
+/// The components of an RSA private key as big-endian unsigned integers, like in a JWK.
+pub(crate) struct RsaComponents {
+    pub(crate) n: Vec<u8>,
+    pub(crate) e: Vec<u8>,
+    pub(crate) d: Vec<u8>,
+    pub(crate) p: Vec<u8>,
+    pub(crate) q: Vec<u8>,
+    pub(crate) dp: Vec<u8>,
+    pub(crate) dq: Vec<u8>,
+    pub(crate) qi: Vec<u8>,
+}
+impl Drop for RsaComponents {
+    fn drop(&mut self) {
+        self.d.zeroize();
+        self.p.zeroize();
+        self.q.zeroize();
+        self.dp.zeroize();
+        self.dq.zeroize();
+        self.qi.zeroize();
+    }
+}
+
+/// The primitives the rest of the crate is built on.
+///
+/// RSA encryption is OAEP (SHA-1, as WebCrypto clients use it), signatures are RSASSA-PKCS1-v1_5 with SHA-256.
+pub(crate) trait Backend {
+    type PrivateKey: Clone + Send + Sync;
+    type PublicKey: Clone + Send + Sync;
+
+    fn private_key_from_components(components: &RsaComponents) -> Result<Self::PrivateKey>;
+    fn private_key_components(key: &Self::PrivateKey) -> Result<RsaComponents>;
+    fn public_key_from_components(n: &[u8], e: &[u8]) -> Result<Self::PublicKey>;
+    /// `(n, e)`
+    fn public_key_components(key: &Self::PublicKey) -> (Vec<u8>, Vec<u8>);
+    fn public_key_of(key: &Self::PrivateKey) -> Result<Self::PublicKey>;
+    /// PKCS#8 PEM
+    fn private_key_to_pem(key: &Self::PrivateKey) -> Result<SecretString>;
+    /// PKCS#8 or PKCS#1 PEM
+    fn private_key_from_pem(pem: &str) -> Result<Self::PrivateKey>;
+    /// SubjectPublicKeyInfo PEM
+    fn public_key_to_pem(key: &Self::PublicKey) -> Result<String>;
+    /// SubjectPublicKeyInfo or PKCS#1 PEM
+    fn public_key_from_pem(pem: &str) -> Result<Self::PublicKey>;
+    /// SubjectPublicKeyInfo DER
+    fn public_key_to_der(key: &Self::PublicKey) -> Result<Vec<u8>>;
+
+    fn rsa_encrypt(key: &Self::PublicKey, data: &[u8]) -> Result<Vec<u8>>;
+    fn rsa_decrypt(key: &Self::PrivateKey, data: &[u8]) -> Result<SecretBytes>;
+    fn rsa_sign(key: &Self::PrivateKey, data: &[u8]) -> Result<Vec<u8>>;
+    /// Malformed signatures are reported as `false`.
+    fn rsa_verify(key: &Self::PublicKey, data: &[u8], signature: &[u8]) -> bool;
+
+    /// A missing IV is treated as all zeros.
+    fn symmetric_encrypt(cipher: EncryptionFunction, key: &[u8], iv: Option<&[u8]>, data: &[u8]) -> Result<Vec<u8>>;
+    fn symmetric_decrypt(cipher: EncryptionFunction, key: &[u8], iv: Option<&[u8]>, data: &[u8]) -> Result<Vec<u8>>;
+    fn pbkdf2(prf: Prf, password: &[u8], salt: &[u8], iterations: usize, output: &mut [u8]) -> Result<()>;
+    fn sha256(data: &[u8]) -> Result<Vec<u8>>;
+    fn random_bytes(output: &mut [u8]) -> Result<()>;
+}
+
+/// An RSA private key of the active crypto backend.
+#[derive(Clone)]
+pub struct PrivateKey(pub(crate) <ActiveBackend as Backend>::PrivateKey);
+impl PrivateKey {
+    pub fn public_key(&self) -> Result<PublicKey> {
+        Ok(PublicKey(ActiveBackend::public_key_of(&self.0)?))
+    }
+    /// PKCS#8 PEM document of the key.
+    pub fn to_pem(&self) -> Result<SecretString> {
+        ActiveBackend::private_key_to_pem(&self.0)
+    }
+    /// Reads a PKCS#8 or PKCS#1 PEM document.
+    pub fn from_pem(pem: &str) -> Result<Self> {
+        Ok(Self(ActiveBackend::private_key_from_pem(pem)?))
+    }
+    pub(crate) fn from_components(components: &RsaComponents) -> Result<Self> {
+        Ok(Self(ActiveBackend::private_key_from_components(components)?))
+    }
+    pub(crate) fn components(&self) -> Result<RsaComponents> {
+        ActiveBackend::private_key_components(&self.0)
+    }
+}
+impl Debug for PrivateKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PrivateKey([REDACTED])")
+    }
+}
+#[cfg(feature = "openssl")]
+impl From<openssl::rsa::Rsa<openssl::pkey::Private>> for PrivateKey {
+    fn from(key: openssl::rsa::Rsa<openssl::pkey::Private>) -> Self {
+        Self(key)
+    }
+}
+
+/// An RSA public key of the active crypto backend.
+#[derive(Clone)]
+pub struct PublicKey(pub(crate) <ActiveBackend as Backend>::PublicKey);
+impl PublicKey {
+    /// SubjectPublicKeyInfo PEM document of the key.
+    pub fn to_pem(&self) -> Result<String> {
+        ActiveBackend::public_key_to_pem(&self.0)
+    }
+    /// Reads a SubjectPublicKeyInfo or PKCS#1 PEM document.
+    pub fn from_pem(pem: &str) -> Result<Self> {
+        Ok(Self(ActiveBackend::public_key_from_pem(pem)?))
+    }
+    /// SubjectPublicKeyInfo DER encoding of the key.
+    pub fn to_der(&self) -> Result<Vec<u8>> {
+        ActiveBackend::public_key_to_der(&self.0)
+    }
+    /// Checks an RSASSA-PKCS1-v1_5 SHA-256 signature.
+    pub fn verify(&self, data: &[u8], signature: &[u8]) -> bool {
+        ActiveBackend::rsa_verify(&self.0, data, signature)
+    }
+    pub(crate) fn from_components(n: &[u8], e: &[u8]) -> Result<Self> {
+        Ok(Self(ActiveBackend::public_key_from_components(n, e)?))
+    }
+    /// `(n, e)`
+    pub(crate) fn components(&self) -> (Vec<u8>, Vec<u8>) {
+        ActiveBackend::public_key_components(&self.0)
+    }
+}
+impl Debug for PublicKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let fingerprint = crate::key_store::fingerprint(self).unwrap_or_else(|_| "<invalid>".to_string());
+        write!(f, "PublicKey({fingerprint})")
+    }
+}
+impl PartialEq for PublicKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.components() == other.components()
+    }
+}
+impl Eq for PublicKey {}
+#[cfg(feature = "openssl")]
+impl From<openssl::rsa::Rsa<openssl::pkey::Public>> for PublicKey {
+    fn from(key: openssl::rsa::Rsa<openssl::pkey::Public>) -> Self {
+        Self(key)
+    }
+}
+
+pub(crate) fn rsa_encrypt(key: &PublicKey, data: &[u8]) -> Result<Vec<u8>> {
+    ActiveBackend::rsa_encrypt(&key.0, data)
+}
+pub(crate) fn rsa_decrypt(key: &PrivateKey, data: &[u8]) -> Result<SecretBytes> {
+    ActiveBackend::rsa_decrypt(&key.0, data)
+}
+pub(crate) fn rsa_sign(key: &PrivateKey, data: &[u8]) -> Result<Vec<u8>> {
+    ActiveBackend::rsa_sign(&key.0, data)
+}
+pub(crate) fn symmetric_encrypt(cipher: EncryptionFunction, key: &[u8], iv: Option<&[u8]>, data: &[u8]) -> Result<Vec<u8>> {
+    ActiveBackend::symmetric_encrypt(cipher, key, iv, data)
+}
+pub(crate) fn symmetric_decrypt(cipher: EncryptionFunction, key: &[u8], iv: Option<&[u8]>, data: &[u8]) -> Result<Vec<u8>> {
+    ActiveBackend::symmetric_decrypt(cipher, key, iv, data)
+}
+pub(crate) fn pbkdf2(prf: Prf, password: &[u8], salt: &[u8], iterations: usize, output: &mut [u8]) -> Result<()> {
+    ActiveBackend::pbkdf2(prf, password, salt, iterations, output)
+}
+pub(crate) fn sha256(data: &[u8]) -> Result<Vec<u8>> {
+    ActiveBackend::sha256(data)
+}
+pub(crate) fn random_bytes(output: &mut [u8]) -> Result<()> {
+    ActiveBackend::random_bytes(output)
+}
@@ -0,0 +1,144 @@
+use openssl::bn::{BigNum, BigNumRef};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private, Public};
+use openssl::rsa::{Padding, Rsa};
+use openssl::sign::{Signer, Verifier};
+use openssl::symm::Cipher;
+use crate::crypto::{Backend, EncryptionFunction, Prf, RsaComponents};
+use crate::errors::Errors;
+use crate::secret::{SecretBytes, SecretString};
+use crate::Result;
+
+pub(crate) struct OpenSsl;
+
+fn message_digest(prf: Prf) -> MessageDigest {
+    match prf {
+        Prf::HmacSha1 => MessageDigest::sha1(),
+        Prf::HmacSha256 => MessageDigest::sha256(),
+        Prf::HmacSha384 => MessageDigest::sha384(),
+        Prf::HmacSha512 => MessageDigest::sha512(),
+    }
+}
+fn cipher(function: EncryptionFunction) -> Cipher {
+    match function {
+        EncryptionFunction::Aes256Cbc => Cipher::aes_256_cbc(),
+    }
+}
+fn zero_iv(function: EncryptionFunction, iv: Option<&[u8]>) -> Vec<u8> {
+    match iv {
+        Some(iv) => iv.to_vec(),
+        None => vec![0; cipher(function).iv_len().unwrap_or_default()],
+    }
+}
+fn big_num(bytes: &[u8]) -> Result<BigNum> {
+    Ok(BigNum::from_slice(bytes)?)
+}
+fn bytes(number: Option<&BigNumRef>) -> Result<Vec<u8>> {
+    number.map(|number| number.to_vec())
+        .ok_or_else(|| Errors::ValueError("private key is missing its CRT parameters".to_string()))
+}
+
+impl Backend for OpenSsl {
+    type PrivateKey = Rsa<Private>;
+    type PublicKey = Rsa<Public>;
+
+    fn private_key_from_components(components: &RsaComponents) -> Result<Self::PrivateKey> {
+        Ok(openssl::rsa::RsaPrivateKeyBuilder::new(
+            big_num(&components.n)?,
+            big_num(&components.e)?,
+            big_num(&components.d)?,
+        )?.set_factors(
+            big_num(&components.p)?,
+            big_num(&components.q)?,
+        )?.set_crt_params(
+            big_num(&components.dp)?,
+            big_num(&components.dq)?,
+            big_num(&components.qi)?,
+        )?.build())
+    }
+    fn private_key_components(key: &Self::PrivateKey) -> Result<RsaComponents> {
+        Ok(RsaComponents {
+            n: key.n().to_vec(),
+            e: key.e().to_vec(),
+            d: key.d().to_vec(),
+            p: bytes(key.p())?,
+            q: bytes(key.q())?,
+            dp: bytes(key.dmp1())?,
+            dq: bytes(key.dmq1())?,
+            qi: bytes(key.iqmp())?,
+        })
+    }
+    fn public_key_from_components(n: &[u8], e: &[u8]) -> Result<Self::PublicKey> {
+        Ok(Rsa::from_public_components(big_num(n)?, big_num(e)?)?)
+    }
+    fn public_key_components(key: &Self::PublicKey) -> (Vec<u8>, Vec<u8>) {
+        (key.n().to_vec(), key.e().to_vec())
+    }
+    fn public_key_of(key: &Self::PrivateKey) -> Result<Self::PublicKey> {
+        Ok(Rsa::from_public_components(key.n().to_owned()?, key.e().to_owned()?)?)
+    }
+    fn private_key_to_pem(key: &Self::PrivateKey) -> Result<SecretString> {
+        let pem = PKey::from_rsa(key.clone())?.private_key_to_pem_pkcs8()?;
+        Ok(String::from_utf8(pem)?.into())
+    }
+    fn private_key_from_pem(pem: &str) -> Result<Self::PrivateKey> {
+        Ok(PKey::private_key_from_pem(pem.as_bytes())?.rsa()?)
+    }
+    fn public_key_to_pem(key: &Self::PublicKey) -> Result<String> {
+        Ok(String::from_utf8(key.public_key_to_pem()?)?)
+    }
+    fn public_key_from_pem(pem: &str) -> Result<Self::PublicKey> {
+        Ok(Rsa::public_key_from_pem(pem.as_bytes())
+            .or_else(|_| Rsa::public_key_from_pem_pkcs1(pem.as_bytes()))?)
+    }
+    fn public_key_to_der(key: &Self::PublicKey) -> Result<Vec<u8>> {
+        Ok(key.public_key_to_der()?)
+    }
+
+    fn rsa_encrypt(key: &Self::PublicKey, data: &[u8]) -> Result<Vec<u8>> {
+        let mut encrypted = vec![0; key.size() as usize];
+        let len = key.public_encrypt(data, &mut encrypted, Padding::PKCS1_OAEP)?;
+        encrypted.truncate(len);
+        Ok(encrypted)
+    }
+    fn rsa_decrypt(key: &Self::PrivateKey, data: &[u8]) -> Result<SecretBytes> {
+        let mut decrypted = SecretBytes::zeroed(key.size() as usize);
+        let len = key.private_decrypt(data, decrypted.expose_mut(), Padding::PKCS1_OAEP)?;
+        decrypted.truncate(len);
+        Ok(decrypted)
+    }
+    fn rsa_sign(key: &Self::PrivateKey, data: &[u8]) -> Result<Vec<u8>> {
+        let key = PKey::from_rsa(key.clone())?;
+        let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+        signer.update(data)?;
+        Ok(signer.sign_to_vec()?)
+    }
+    fn rsa_verify(key: &Self::PublicKey, data: &[u8], signature: &[u8]) -> bool {
+        // openssl reports malformed signatures as errors
+        let verify = || -> Result<bool> {
+            let key = PKey::from_rsa(key.clone())?;
+            let mut verifier = Verifier::new(MessageDigest::sha256(), &key)?;
+            verifier.update(data)?;
+            Ok(verifier.verify(signature)?)
+        };
+        verify().unwrap_or(false)
+    }
+
+    fn symmetric_encrypt(function: EncryptionFunction, key: &[u8], iv: Option<&[u8]>, data: &[u8]) -> Result<Vec<u8>> {
+        let iv = zero_iv(function, iv);
+        Ok(openssl::symm::encrypt(cipher(function), key, Some(&*iv), data)?)
+    }
+    fn symmetric_decrypt(function: EncryptionFunction, key: &[u8], iv: Option<&[u8]>, data: &[u8]) -> Result<Vec<u8>> {
+        let iv = zero_iv(function, iv);
+        Ok(openssl::symm::decrypt(cipher(function), key, Some(&*iv), data)?)
+    }
+    fn pbkdf2(prf: Prf, password: &[u8], salt: &[u8], iterations: usize, output: &mut [u8]) -> Result<()> {
+        Ok(openssl::pkcs5::pbkdf2_hmac(password, salt, iterations, message_digest(prf), output)?)
+    }
+    fn sha256(data: &[u8]) -> Result<Vec<u8>> {
+        Ok(openssl::hash::hash(MessageDigest::sha256(), data)?.to_vec())
+    }
+    fn random_bytes(output: &mut [u8]) -> Result<()> {
+        Ok(openssl::rand::rand_bytes(output)?)
+    }
+}
@@ -0,0 +1,137 @@
+//! Only used if the `openssl` feature is disabled, but still compiled with it so both backends can be checked against each other.
+#![cfg_attr(feature = "openssl", allow(dead_code))]
+use cbc::cipher::block_padding::Pkcs7;
+use cbc::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use rand_core::{OsRng, RngCore};
+use rsa::pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey};
+use rsa::pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey, LineEnding};
+use rsa::traits::{PrivateKeyParts, PublicKeyParts};
+use rsa::{BigUint, Oaep, Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use crate::crypto::{Backend, EncryptionFunction, Prf, RsaComponents};
+use crate::errors::Errors;
+use crate::secret::{SecretBytes, SecretString};
+use crate::Result;
+
+pub(crate) struct RustCrypto;
+
+type Aes256CbcEncryptor = cbc::Encryptor<aes::Aes256>;
+type Aes256CbcDecryptor = cbc::Decryptor<aes::Aes256>;
+
+fn error(e: impl std::fmt::Display) -> Errors {
+    Errors::CryptoError(e.to_string())
+}
+fn big_uint(bytes: &[u8]) -> BigUint {
+    BigUint::from_bytes_be(bytes)
+}
+fn zero_iv(iv: Option<&[u8]>) -> Vec<u8> {
+    iv.map(|iv| iv.to_vec()).unwrap_or_else(|| vec![0; 16])
+}
+
+impl Backend for RustCrypto {
+    type PrivateKey = RsaPrivateKey;
+    type PublicKey = RsaPublicKey;
+
+    fn private_key_from_components(components: &RsaComponents) -> Result<Self::PrivateKey> {
+        // the CRT parameters are derived from the primes again by `precompute`
+        let mut key = RsaPrivateKey::from_components(
+            big_uint(&components.n),
+            big_uint(&components.e),
+            big_uint(&components.d),
+            vec![big_uint(&components.p), big_uint(&components.q)],
+        ).map_err(error)?;
+        key.precompute().map_err(error)?;
+        Ok(key)
+    }
+    fn private_key_components(key: &Self::PrivateKey) -> Result<RsaComponents> {
+        let missing = || Errors::ValueError("private key is missing its CRT parameters".to_string());
+        let [p, q] = key.primes() else {
+            return Err(Errors::ValueError("only two-prime RSA keys are supported".to_string()));
+        };
+        Ok(RsaComponents {
+            n: key.n().to_bytes_be(),
+            e: key.e().to_bytes_be(),
+            d: key.d().to_bytes_be(),
+            p: p.to_bytes_be(),
+            q: q.to_bytes_be(),
+            dp: key.dp().ok_or_else(missing)?.to_bytes_be(),
+            dq: key.dq().ok_or_else(missing)?.to_bytes_be(),
+            qi: key.crt_coefficient().ok_or_else(missing)?.to_bytes_be(),
+        })
+    }
+    fn public_key_from_components(n: &[u8], e: &[u8]) -> Result<Self::PublicKey> {
+        RsaPublicKey::new(big_uint(n), big_uint(e)).map_err(error)
+    }
+    fn public_key_components(key: &Self::PublicKey) -> (Vec<u8>, Vec<u8>) {
+        (key.n().to_bytes_be(), key.e().to_bytes_be())
+    }
+    fn public_key_of(key: &Self::PrivateKey) -> Result<Self::PublicKey> {
+        Ok(key.to_public_key())
+    }
+    fn private_key_to_pem(key: &Self::PrivateKey) -> Result<SecretString> {
+        Ok(key.to_pkcs8_pem(LineEnding::LF).map_err(error)?.as_str().into())
+    }
+    fn private_key_from_pem(pem: &str) -> Result<Self::PrivateKey> {
+        RsaPrivateKey::from_pkcs8_pem(pem)
+            .or_else(|_| RsaPrivateKey::from_pkcs1_pem(pem))
+            .map_err(error)
+    }
+    fn public_key_to_pem(key: &Self::PublicKey) -> Result<String> {
+        key.to_public_key_pem(LineEnding::LF).map_err(error)
+    }
+    fn public_key_from_pem(pem: &str) -> Result<Self::PublicKey> {
+        RsaPublicKey::from_public_key_pem(pem)
+            .or_else(|_| RsaPublicKey::from_pkcs1_pem(pem))
+            .map_err(error)
+    }
+    fn public_key_to_der(key: &Self::PublicKey) -> Result<Vec<u8>> {
+        Ok(key.to_public_key_der().map_err(error)?.into_vec())
+    }
+
+    fn rsa_encrypt(key: &Self::PublicKey, data: &[u8]) -> Result<Vec<u8>> {
+        key.encrypt(&mut OsRng, Oaep::new::<Sha1>(), data).map_err(error)
+    }
+    fn rsa_decrypt(key: &Self::PrivateKey, data: &[u8]) -> Result<SecretBytes> {
+        Ok(key.decrypt(Oaep::new::<Sha1>(), data).map_err(error)?.into())
+    }
+    fn rsa_sign(key: &Self::PrivateKey, data: &[u8]) -> Result<Vec<u8>> {
+        key.sign(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(data)).map_err(error)
+    }
+    fn rsa_verify(key: &Self::PublicKey, data: &[u8], signature: &[u8]) -> bool {
+        key.verify(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(data), signature).is_ok()
+    }
+
+    fn symmetric_encrypt(function: EncryptionFunction, key: &[u8], iv: Option<&[u8]>, data: &[u8]) -> Result<Vec<u8>> {
+        match function {
+            EncryptionFunction::Aes256Cbc => Ok(Aes256CbcEncryptor::new_from_slices(key, &zero_iv(iv))
+                .map_err(error)?
+                .encrypt_padded_vec_mut::<Pkcs7>(data)),
+        }
+    }
+    fn symmetric_decrypt(function: EncryptionFunction, key: &[u8], iv: Option<&[u8]>, data: &[u8]) -> Result<Vec<u8>> {
+        match function {
+            EncryptionFunction::Aes256Cbc => Aes256CbcDecryptor::new_from_slices(key, &zero_iv(iv))
+                .map_err(error)?
+                .decrypt_padded_vec_mut::<Pkcs7>(data)
+                .map_err(error),
+        }
+    }
+    fn pbkdf2(prf: Prf, password: &[u8], salt: &[u8], iterations: usize, output: &mut [u8]) -> Result<()> {
+        let iterations = u32::try_from(iterations)
+            .map_err(|_| Errors::ValueError(format!("{iterations} PBKDF2 iterations are too many")))?;
+        match prf {
+            Prf::HmacSha1 => pbkdf2::pbkdf2_hmac::<Sha1>(password, salt, iterations, output),
+            Prf::HmacSha256 => pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, iterations, output),
+            Prf::HmacSha384 => pbkdf2::pbkdf2_hmac::<Sha384>(password, salt, iterations, output),
+            Prf::HmacSha512 => pbkdf2::pbkdf2_hmac::<Sha512>(password, salt, iterations, output),
+        }
+        Ok(())
+    }
+    fn sha256(data: &[u8]) -> Result<Vec<u8>> {
+        Ok(Sha256::digest(data).to_vec())
+    }
+    fn random_bytes(output: &mut [u8]) -> Result<()> {
+        OsRng.try_fill_bytes(output).map_err(error)
+    }
+}
@@ -0,0 +1,215 @@
+//! Fixed vectors every backend has to reproduce, and cross checks between the backends if both are compiled.
+//! The vectors come from RFC 6070, NIST SP 800-38A and Python's `hashlib`/`cryptography`; the RSA keys are in `testdata/`.
+//! The per-backend tests also run on `wasm32-unknown-unknown` (see `.cargo/config.toml`).
+use super::*;
+#[cfg(feature = "openssl")]
+use super::openssl_backend::OpenSsl;
+#[cfg(feature = "rustcrypto")]
+use super::rustcrypto_backend::RustCrypto;
+
+const ENCRYPTION_KEY: &str = include_str!("../../testdata/encryption_key.pem");
+const SIGNING_KEY: &str = include_str!("../../testdata/signing_key.pem");
+
+/// `"chat key"`, RSA-OAEP (SHA-1) encrypted for [`ENCRYPTION_KEY`].
+const OAEP_CIPHERTEXT: &str = "a81090c0fb58370fb875ef942ce94f6689272ac95522d3d5ae6bfc61b2852a1735533cedff793f65444daa1956042b38610488ba6a743f8605312ac34a3d4f57bc65bc6e0f690769af1a34d3a7cf9f06da3a1e579a16752bce898fff40f56bc5f154c50aaff0ada9bbb6447da7038dd0ceb49cd816a5530c4116b5ca9df84b92d5eebc9f045798487e4d19901e63e646d3150e83304bfc0c0d967e549052b2b6ee26b9461ee339efa468f5313ca7da9b849de50504f92971e6ca2893ed398beb9458c3b2f6241e054ac549b72b6cacdf97fd4f0d79fdf9830573813943ce1a09140673d75b17e3b74b298f4e02cbe666aba3c9ccb2ca13b3e7fd455d49e7ec94";
+/// `"signed message"`, signed (RSASSA-PKCS1-v1_5, SHA-256) with [`SIGNING_KEY`].
+const SIGNATURE: &str = "2e553474b38d40a2863d7a5b3f9930fc1f6c493cddbaa1cccc6892c1cf8c0d1eb7a41aa643e6c708387f7ac0978820f638ec091aac0f249abf3ec663201c7b6942f50a228208cd042e5d2a5b37cca1b6c416a088d49b7d8b26ce6ada1fcd7f7ce74d1402536aabcf6d3ed9882f3742912403532c2ecd413baf2c54cb6009d9dea75e4b4c3878e9117823b1f663e551a642ee6d2d2b3a753c102e7ece3ac49eebc5a3d25cde3bff876c0c16c2d0e6da88833a52738a98a44a18cfb0a619fba519edbee53b2cde190a914912c1ad17c2a7e0f350f8f15d1495ac294c84dee95d57744b9350d27c8497df3c182360b1a384a84a05fbfd94461dea0b26b5175086ca";
+
+const AES_KEY: &str = "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4";
+
+fn unhex(data: &str) -> Vec<u8> {
+    hex::decode(data).unwrap()
+}
+
+fn check_pbkdf2<B: Backend>() {
+    // password "password", salt "salt", 2 iterations; the SHA-1 vector is from RFC 6070
+    let vectors = [
+        (Prf::HmacSha1, "ea6c014dc72d6f8ccd1ed92ace1d41f0d8de8957"),
+        (Prf::HmacSha256, "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43"),
+        (Prf::HmacSha384, "54f775c6d790f21930459162fc535dbf04a939185127016a04176a0730c6f1f4fb48832ad1261baadd2cedd50814b1c8"),
+        (Prf::HmacSha512, "e1d9c16aa681708a45f5c7c4e215ceb66e011a2e9f0040713f18aefdb866d53cf76cab2868a39b9f7840edce4fef5a82be67335c77a6068e04112754f27ccf4e"),
+    ];
+    for (prf, expected) in vectors {
+        let mut output = vec![0; expected.len() / 2];
+        B::pbkdf2(prf, b"password", b"salt", 2, &mut output).unwrap();
+        assert_eq!(hex::encode(output), expected, "{prf}");
+    }
+}
+fn check_aes<B: Backend>() {
+    let cipher = EncryptionFunction::Aes256Cbc;
+    let key = unhex(AES_KEY);
+    // NIST SP 800-38A F.2.5, block 1, followed by the PKCS#7 padding block
+    let iv = unhex("000102030405060708090a0b0c0d0e0f");
+    let plaintext = unhex("6bc1bee22e409f96e93d7e117393172a");
+    let ciphertext = unhex("f58c4c04d6e5f1ba779eabfb5f7bfbd6485a5c81519cf378fa36d42b8547edc0");
+    assert_eq!(B::symmetric_encrypt(cipher, &key, Some(&iv), &plaintext).unwrap(), ciphertext);
+    assert_eq!(B::symmetric_decrypt(cipher, &key, Some(&iv), &ciphertext).unwrap(), plaintext);
+    // without an IV, zeros are used
+    let ciphertext = unhex("a50b89c5f6702a5b2d2932c8fd5b7c4e");
+    assert_eq!(B::symmetric_encrypt(cipher, &key, None, b"no iv").unwrap(), ciphertext);
+    assert_eq!(B::symmetric_decrypt(cipher, &key, None, &ciphertext).unwrap(), b"no iv");
+}
+fn check_sha256<B: Backend>() {
+    assert_eq!(hex::encode(B::sha256(b"abc").unwrap()), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+}
+fn check_rsa<B: Backend>() {
+    let encryption_key = B::private_key_from_pem(ENCRYPTION_KEY).unwrap();
+    let decrypted = B::rsa_decrypt(&encryption_key, &unhex(OAEP_CIPHERTEXT)).unwrap();
+    assert_eq!(decrypted.expose(), b"chat key");
+    let public_key = B::public_key_of(&encryption_key).unwrap();
+    let encrypted = B::rsa_encrypt(&public_key, b"round trip").unwrap();
+    assert_eq!(B::rsa_decrypt(&encryption_key, &encrypted).unwrap().expose(), b"round trip");
+
+    let signing_key = B::private_key_from_pem(SIGNING_KEY).unwrap();
+    let public_key = B::public_key_of(&signing_key).unwrap();
+    // PKCS#1 v1.5 signatures are deterministic
+    assert_eq!(hex::encode(B::rsa_sign(&signing_key, b"signed message").unwrap()), SIGNATURE);
+    assert!(B::rsa_verify(&public_key, b"signed message", &unhex(SIGNATURE)));
+    assert!(!B::rsa_verify(&public_key, b"signed massage", &unhex(SIGNATURE)));
+    assert!(!B::rsa_verify(&public_key, b"signed message", b"not a signature"));
+}
+fn check_key_encodings<B: Backend>() {
+    let private_key = B::private_key_from_pem(ENCRYPTION_KEY).unwrap();
+    let components = B::private_key_components(&private_key).unwrap();
+    let restored = B::private_key_from_components(&components).unwrap();
+    assert_eq!(B::private_key_to_pem(&restored).unwrap().expose().trim(), ENCRYPTION_KEY.trim());
+
+    let public_key = B::public_key_of(&private_key).unwrap();
+    let pem = B::public_key_to_pem(&public_key).unwrap();
+    let parsed = B::public_key_from_pem(&pem).unwrap();
+    assert_eq!(B::public_key_components(&parsed), (components.n.clone(), components.e.clone()));
+    let from_components = B::public_key_from_components(&components.n, &components.e).unwrap();
+    assert_eq!(B::public_key_to_der(&from_components).unwrap(), B::public_key_to_der(&public_key).unwrap());
+}
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn algorithm_names() {
+    for name in ["sha256", "SHA-256", "HMAC-SHA-256", "hmac_sha256"] {
+        assert_eq!(name.parse::<Prf>().unwrap(), Prf::HmacSha256);
+    }
+    assert_eq!("SHA1".parse::<Prf>().unwrap(), Prf::HmacSha1);
+    assert_eq!("hmac-sha512".parse::<Prf>().unwrap(), Prf::HmacSha512);
+    assert!(matches!("md5".parse::<Prf>(), Err(Errors::UnsupportedAlgorithm(_))));
+    for name in ["aes-256-cbc", "AES-CBC", "aes_cbc_256"] {
+        assert_eq!(name.parse::<EncryptionFunction>().unwrap(), EncryptionFunction::Aes256Cbc);
+    }
+    assert!(matches!("aes-256-gcm".parse::<EncryptionFunction>(), Err(Errors::UnsupportedAlgorithm(_))));
+    // the names we write can be read again
+    for prf in [Prf::HmacSha1, Prf::HmacSha256, Prf::HmacSha384, Prf::HmacSha512] {
+        assert_eq!(prf.to_string().parse::<Prf>().unwrap(), prf);
+    }
+    assert_eq!(EncryptionFunction::Aes256Cbc.to_string().parse::<EncryptionFunction>().unwrap(), EncryptionFunction::Aes256Cbc);
+}
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn unknown_chat_ciphers_are_unsupported() {
+    use crate::test_support::channel;
+    use crate::types::chats::Chat;
+    use serde_json::json;
+
+    assert_eq!(channel(json!({})).cipher().unwrap(), EncryptionFunction::Aes256Cbc);
+    assert_eq!(channel(json!({"crypto_properties": {"encryption_func": "aes-256-cbc"}})).cipher().unwrap(), EncryptionFunction::Aes256Cbc);
+    assert!(matches!(channel(json!({"crypto_properties": {"encryption_func": "chacha20"}})).cipher(), Err(Errors::UnsupportedAlgorithm(_))));
+    assert!(matches!(channel(json!({"crypto_properties": {"encryption_func": 1}})).cipher(), Err(Errors::UnsupportedAlgorithm(_))));
+}
+
+/// Failures look the same whichever backend is compiled in.
+fn check_errors<B: Backend>() {
+    let key = unhex(AES_KEY);
+    let truncated = unhex("f58c4c04d6e5f1ba779eabfb5f7bfb");
+    assert!(matches!(B::symmetric_decrypt(EncryptionFunction::Aes256Cbc, &key, None, &truncated), Err(Errors::CryptoError(_))));
+    let encryption_key = B::private_key_from_pem(ENCRYPTION_KEY).unwrap();
+    let mut tampered = unhex(OAEP_CIPHERTEXT);
+    tampered[0] ^= 1;
+    assert!(matches!(B::rsa_decrypt(&encryption_key, &tampered), Err(Errors::CryptoError(_))));
+}
+
+macro_rules! backend_tests {
+    ($module:ident, $backend:ty) => {
+        mod $module {
+            use super::*;
+
+            #[cfg_attr(not(target_arch = "wasm32"), test)]
+            #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+            fn pbkdf2() {
+                check_pbkdf2::<$backend>();
+            }
+            #[cfg_attr(not(target_arch = "wasm32"), test)]
+            #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+            fn aes_256_cbc() {
+                check_aes::<$backend>();
+            }
+            #[cfg_attr(not(target_arch = "wasm32"), test)]
+            #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+            fn sha256() {
+                check_sha256::<$backend>();
+            }
+            #[cfg_attr(not(target_arch = "wasm32"), test)]
+            #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+            fn rsa() {
+                check_rsa::<$backend>();
+            }
+            #[cfg_attr(not(target_arch = "wasm32"), test)]
+            #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+            fn key_encodings() {
+                check_key_encodings::<$backend>();
+            }
+            #[cfg_attr(not(target_arch = "wasm32"), test)]
+            #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+            fn errors() {
+                check_errors::<$backend>();
+            }
+        }
+    };
+}
+#[cfg(feature = "openssl")]
+backend_tests!(openssl, OpenSsl);
+#[cfg(feature = "rustcrypto")]
+backend_tests!(rustcrypto, RustCrypto);
+
+/// What one backend produces, the other has to accept.
+#[cfg(all(feature = "openssl", feature = "rustcrypto"))]
+mod cross {
+    use super::*;
+
+    #[test]
+    fn rsa_between_backends() {
+        let openssl_key = OpenSsl::private_key_from_pem(ENCRYPTION_KEY).unwrap();
+        let rustcrypto_key = RustCrypto::private_key_from_pem(ENCRYPTION_KEY).unwrap();
+        let encrypted = OpenSsl::rsa_encrypt(&OpenSsl::public_key_of(&openssl_key).unwrap(), b"to rustcrypto").unwrap();
+        assert_eq!(RustCrypto::rsa_decrypt(&rustcrypto_key, &encrypted).unwrap().expose(), b"to rustcrypto");
+        let encrypted = RustCrypto::rsa_encrypt(&RustCrypto::public_key_of(&rustcrypto_key).unwrap(), b"to openssl").unwrap();
+        assert_eq!(OpenSsl::rsa_decrypt(&openssl_key, &encrypted).unwrap().expose(), b"to openssl");
+
+        let openssl_key = OpenSsl::private_key_from_pem(SIGNING_KEY).unwrap();
+        let rustcrypto_key = RustCrypto::private_key_from_pem(SIGNING_KEY).unwrap();
+        let signature = OpenSsl::rsa_sign(&openssl_key, b"cross signed").unwrap();
+        assert_eq!(RustCrypto::rsa_sign(&rustcrypto_key, b"cross signed").unwrap(), signature);
+        assert!(RustCrypto::rsa_verify(&RustCrypto::public_key_of(&rustcrypto_key).unwrap(), b"cross signed", &signature));
+    }
+    #[test]
+    fn key_components_between_backends() {
+        let openssl_components = OpenSsl::private_key_components(&OpenSsl::private_key_from_pem(ENCRYPTION_KEY).unwrap()).unwrap();
+        let rustcrypto_key = RustCrypto::private_key_from_components(&openssl_components).unwrap();
+        let rustcrypto_components = RustCrypto::private_key_components(&rustcrypto_key).unwrap();
+        assert_eq!(rustcrypto_components.d, openssl_components.d);
+        assert_eq!(rustcrypto_components.qi, openssl_components.qi);
+        let openssl_public = OpenSsl::public_key_of(&OpenSsl::private_key_from_components(&rustcrypto_components).unwrap()).unwrap();
+        let rustcrypto_public = RustCrypto::public_key_of(&rustcrypto_key).unwrap();
+        assert_eq!(OpenSsl::public_key_to_der(&openssl_public).unwrap(), RustCrypto::public_key_to_der(&rustcrypto_public).unwrap());
+    }
+    #[test]
+    fn symmetric_between_backends() {
+        let cipher = EncryptionFunction::Aes256Cbc;
+        let mut key = [0; 32];
+        let mut iv = [0; 16];
+        OpenSsl::random_bytes(&mut key).unwrap();
+        RustCrypto::random_bytes(&mut iv).unwrap();
+        for length in [0, 1, 15, 16, 17, 100] {
+            let data = vec![0x42; length];
+            let encrypted = OpenSsl::symmetric_encrypt(cipher, &key, Some(&iv), &data).unwrap();
+            assert_eq!(RustCrypto::symmetric_encrypt(cipher, &key, Some(&iv), &data).unwrap(), encrypted);
+            assert_eq!(RustCrypto::symmetric_decrypt(cipher, &key, Some(&iv), &encrypted).unwrap(), data);
+        }
+    }
+}
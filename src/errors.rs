@@ -1,19 +1,36 @@
 use std::fmt::{Display, Formatter};
 use std::string::FromUtf8Error;
+use crate::verification::KeyTrust;
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Errors {
     RequestError(reqwest::Error),
     NotJsonError(reqwest::Error),
     JsonDeserializeError(serde_json::Error),
     APIError(String, String, String),
-    EncryptionError(openssl::error::ErrorStack),
+    /// A cryptographic operation failed, with the backend's description of the failure.
+    CryptoError(String),
     ValueError(String),
     Base64Error(base64::DecodeError),
     HexError(hex::FromHexError),
     StringDecodeError(FromUtf8Error),
     OtherErrors(String),
     NotAuthenticated,
+    /// The private keys couldn't be decrypted with the given passphrase.
+    WrongPassphrase,
+    /// The chat (given by its id) is encrypted, but there is no key for the current user.
+    MissingChatKey(String),
+    /// The key for the chat (given by its id) has been requested, but not yet been received.
+    ChatKeyRequested(String),
+    /// The public key of the user (given by its id) didn't pass the [`TrustPolicy`](crate::verification::TrustPolicy).
+    UntrustedKey(String, KeyTrust),
+    /// The server asks for an algorithm or parameter this library doesn't implement.
+    UnsupportedAlgorithm(String),
+    /// Refused to send unencrypted content into the encrypted chat (given by its id).
+    EncryptionRequired(String),
+    /// The company settings or the user's role don't allow the action (given as a description).
+    PermissionDenied(String),
 }
 
 impl Display for Errors {
@@ -29,13 +46,20 @@ impl std::error::Error for Errors {
             Errors::NotJsonError(e) => Some(e),
             Errors::JsonDeserializeError(e) => Some(e),
             Errors::APIError(_, _, _) => None,
-            Errors::EncryptionError(e) => Some(e),
+            Errors::CryptoError(_) => None,
             Errors::ValueError(_) => None,
             Errors::Base64Error(e) => Some(e),
             Errors::HexError(e) => Some(e),
             Errors::StringDecodeError(e) => Some(e),
             Errors::OtherErrors(_) => None,
             Errors::NotAuthenticated => None,
+            Errors::WrongPassphrase => None,
+            Errors::MissingChatKey(_) => None,
+            Errors::ChatKeyRequested(_) => None,
+            Errors::UntrustedKey(_, _) => None,
+            Errors::UnsupportedAlgorithm(_) => None,
+            Errors::EncryptionRequired(_) => None,
+            Errors::PermissionDenied(_) => None,
         }
     }
 }
@@ -49,9 +73,10 @@ impl From<serde_json::Error> for Errors {
         Errors::JsonDeserializeError(e)
     }
 }
+#[cfg(feature = "openssl")]
 impl From<openssl::error::ErrorStack> for Errors {
     fn from(e: openssl::error::ErrorStack) -> Self {
-        Errors::EncryptionError(e)
+        Errors::CryptoError(e.to_string())
     }
 }
 impl From<base64::DecodeError> for Errors {
@@ -0,0 +1,76 @@
+use serde_json::{json, Value};
+use crate::types::chats::messages::{Message, PossibleSender};
+use crate::verification::sender_id;
+
+/// A GeoJSON `Feature` with a `Point` at the location of `message`, or `None` if it has no readable
+/// coordinates (see [`Message::coordinates`]). Sender, time, text and chat become the feature's properties.
+pub fn location_feature(message: &Message) -> Option<Value> {
+    let (latitude, longitude) = message.coordinates()?;
+    let sender_name = match &message.sender {
+        PossibleSender::MessageSender(sender) => {
+            let name = [&sender.first_name, &sender.last_name].into_iter().flatten().cloned().collect::<Vec<_>>().join(" ");
+            Some(name).filter(|name| !name.is_empty())
+        },
+        PossibleSender::String(_) => None,
+    };
+    Some(json!({
+        "type": "Feature",
+        "id": message.id,
+        // GeoJSON positions are longitude first
+        "geometry": {
+            "type": "Point",
+            "coordinates": [longitude, latitude],
+        },
+        "properties": {
+            "sender_id": sender_id(message),
+            "sender_name": sender_name,
+            "time": message.time,
+            "text": message.text.as_deref().filter(|text| !text.is_empty()),
+            "channel_id": message.channel_id,
+            "conversation_id": message.conversation_id,
+        },
+    }))
+}
+/// A GeoJSON `FeatureCollection` of the location messages among `messages`, see [`location_feature`].
+/// Other messages and locations that are still encrypted are left out.
+pub fn feature_collection<'a>(messages: impl IntoIterator<Item = &'a Message>) -> Value {
+    json!({
+        "type": "FeatureCollection",
+        "features": messages.into_iter().filter_map(location_feature).collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::message;
+    use crate::types::chats::messages::encrypt_hex;
+
+    const KEY: [u8; 32] = [7; 32];
+    const IV: [u8; 16] = [1; 16];
+
+    #[test]
+    fn coordinates_are_longitude_first() {
+        let located = message("42", json!({"text": "here", "location": {"latitude": 52.5, "longitude": 13.4}}));
+        let feature = location_feature(&located).unwrap();
+        assert_eq!(feature["geometry"]["coordinates"], json!([13.4, 52.5]));
+        assert_eq!(feature["properties"]["sender_name"], "Test User");
+        assert!(location_feature(&message("42", json!({"text": "no location"}))).is_none());
+    }
+    #[test]
+    fn encrypted_coordinates_are_exported_once_decrypted() {
+        let mut message = message("42", json!({
+            "encrypted": true,
+            "iv": hex::encode(IV),
+            "text": "",
+            "location": {
+                "latitude": encrypt_hex(&KEY, &IV, "52.5").unwrap(),
+                "longitude": encrypt_hex(&KEY, &IV, "13.4").unwrap(),
+            },
+        }));
+        assert_eq!(feature_collection([&message])["features"], json!([]));
+        message.decrypt(&KEY).unwrap();
+        let features = feature_collection([&message]);
+        assert_eq!(features["features"][0]["geometry"]["coordinates"], json!([13.4, 52.5]));
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Deserialize, Serialize};
+use crate::errors::Errors;
+use crate::secret::SecretBytes;
+use crate::state::EncryptionState;
+use crate::types::chats::messages::Message;
+use crate::types::chats::Chat;
+use crate::Result;
+
+/// A chat key in the [`ChatKeyRing`], RSA-encrypted for ourselves (base64) like [`Chat::key`].
+#[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct StoredChatKey {
+    pub key: String,
+    /// Unix timestamp from which on messages are encrypted with this key.
+    pub valid_from: u64,
+}
+
+/// Local history of chat keys, so that messages from before a key rotation can still be decrypted.
+///
+/// Keys are stored encrypted for our own public key, so the ring can be written to disk as is.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChatKeyRing {
+    chats: HashMap<String, Vec<StoredChatKey>>,
+}
+impl ChatKeyRing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Reads a key ring written by [`ChatKeyRing::save`]. A missing file is treated as an empty ring.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(content) => Ok(serde_json::from_str(&content)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(Errors::OtherErrors(format!("couldn't read key ring {}: {e}", path.display()))),
+        }
+    }
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .map_err(|e| Errors::OtherErrors(format!("couldn't write key ring {}: {e}", path.display())))
+    }
+    fn chat_id(chat: &impl Chat) -> String {
+        format!("{}:{}", chat.chat_type(), chat.id())
+    }
+    /// Remembers `key` as the key of `chat` for messages sent from `valid_from` on.
+    pub fn add(&mut self, encryption_state: &EncryptionState, chat: &impl Chat, key: &SecretBytes, valid_from: u64) -> Result<()> {
+        let stored = StoredChatKey {
+            key: BASE64.encode(encryption_state.encrypt(key.expose().to_vec())?),
+            valid_from,
+        };
+        let keys = self.chats.entry(Self::chat_id(chat)).or_default();
+        keys.push(stored);
+        keys.sort_by_key(|key| key.valid_from);
+        Ok(())
+    }
+    pub fn contains_chat(&self, chat: &impl Chat) -> bool {
+        self.chats.get(&Self::chat_id(chat)).is_some_and(|keys| !keys.is_empty())
+    }
+    /// All known keys of `chat` with the time they are valid from, oldest first.
+    pub fn keys(&self, encryption_state: &EncryptionState, chat: &impl Chat) -> Result<Vec<(u64, SecretBytes)>> {
+        self.chats.get(&Self::chat_id(chat))
+            .into_iter()
+            .flatten()
+            .map(|stored| Ok((stored.valid_from, encryption_state.decrypt(stored.key.clone())?)))
+            .collect()
+    }
+}
+
+/// The keys of `chat` from the key ring, plus the chat's current key if someone rotated it since it was last stored.
+pub(crate) fn known_keys(key_ring: &ChatKeyRing, encryption_state: &EncryptionState, chat: &impl Chat) -> Result<Vec<(u64, SecretBytes)>> {
+    let mut keys = key_ring.keys(encryption_state, chat)?;
+    if let Ok(current) = encryption_state.chat_key(chat) {
+        if !keys.iter().any(|(_, key)| *key == current) {
+            let valid_from = keys.last().map(|(valid_from, _)| *valid_from).unwrap_or_default();
+            keys.push((valid_from, current));
+        }
+    }
+    Ok(keys)
+}
+/// Decrypts `message` with the newest key that was valid when it was sent.
+/// If that fails (e.g. because of clock skew around a rotation), the other keys are tried as well.
+/// `keys` has to be sorted by validity, oldest first.
+pub(crate) fn decrypt_with_keys(message: &mut Message, keys: &[(u64, SecretBytes)]) -> Result<()> {
+    if keys.is_empty() {
+        return Ok(());
+    }
+    let time = message.time.as_ref().and_then(|time| time.parse::<u64>().ok()).unwrap_or(u64::MAX);
+    let best = keys.iter().rposition(|(valid_from, _)| *valid_from <= time).unwrap_or(0);
+    let candidates = std::iter::once(best).chain((0..keys.len()).rev().filter(|i| *i != best));
+    let mut last_error = None;
+    for i in candidates {
+        match message.decrypt(keys[i].1.expose()) {
+            Ok(()) => return Ok(()),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    match last_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
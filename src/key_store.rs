@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use crate::crypto::PublicKey;
+use crate::errors::Errors;
+use crate::request_types::about::RSAPublicKey;
+use crate::verification::ContactKeys;
+use crate::Result;
+
+/// Human-readable fingerprint of a public key: the SHA-256 of its DER encoding
+/// as 16 space separated groups of 4 upper case hex digits.
+pub fn fingerprint(key: &PublicKey) -> Result<String> {
+    let digest = crate::crypto::sha256(&key.to_der()?)?;
+    Ok(hex::encode_upper(digest)
+        .as_bytes()
+        .chunks(4)
+        .map(|chunk| String::from_utf8_lossy(chunk).to_string())
+        .collect::<Vec<String>>()
+        .join(" "))
+}
+/// [`fingerprint`] of a JWK encoded public key.
+pub fn jwk_fingerprint(jwk: &str) -> Result<String> {
+    fingerprint(&jwk.parse::<RSAPublicKey>()?.to_key()?)
+}
+/// Strips whitespace and case so fingerprints read out or typed in by people can be compared.
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_uppercase()
+}
+
+/// The key fingerprints remembered for a contact.
+#[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct PinnedKeys {
+    pub public_key: String,
+    pub public_signing_key: Option<String>,
+    /// Whether the user compared the fingerprint with the contact, see [`mark_verified`].
+    pub verified: bool,
+}
+impl PinnedKeys {
+    pub fn from_contact(contact: &ContactKeys) -> Result<Self> {
+        Ok(Self {
+            public_key: fingerprint(&contact.encryption_key()?)?,
+            public_signing_key: match &contact.public_signing_key {
+                Some(key) => Some(jwk_fingerprint(key)?),
+                None => None,
+            },
+            verified: false,
+        })
+    }
+    fn same_keys(&self, other: &PinnedKeys) -> bool {
+        self.public_key == other.public_key && self.public_signing_key == other.public_signing_key
+    }
+}
+
+/// Result of comparing a contact's current keys to the pinned ones.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum KeyEvent {
+    /// The contact hasn't published an encryption key, so nothing was pinned.
+    NoKeys,
+    /// The contact wasn't known before, its keys have been pinned.
+    FirstSeen,
+    /// The keys match the pinned ones.
+    Unchanged { verified: bool },
+    /// The keys differ from the pinned ones. The pin is kept until [`accept_keys`] is called.
+    KeyChanged { pinned: PinnedKeys, current: PinnedKeys },
+}
+
+/// Storage for pinned keys, indexed by user id.
+pub trait KeyStore {
+    fn load(&self, user_id: &str) -> Result<Option<PinnedKeys>>;
+    fn store(&mut self, user_id: &str, keys: PinnedKeys) -> Result<()>;
+}
+/// [`KeyStore`] that only lives as long as the process.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryKeyStore {
+    keys: HashMap<String, PinnedKeys>,
+}
+impl KeyStore for MemoryKeyStore {
+    fn load(&self, user_id: &str) -> Result<Option<PinnedKeys>> {
+        Ok(self.keys.get(user_id).cloned())
+    }
+    fn store(&mut self, user_id: &str, keys: PinnedKeys) -> Result<()> {
+        self.keys.insert(user_id.to_string(), keys);
+        Ok(())
+    }
+}
+/// [`KeyStore`] persisted as a JSON file, replaced on every change.
+#[derive(Clone, Debug)]
+pub struct FileKeyStore {
+    path: PathBuf,
+    keys: HashMap<String, PinnedKeys>,
+}
+impl FileKeyStore {
+    /// Opens the store at `path`. A missing file is treated as an empty store.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let keys = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(Errors::OtherErrors(format!("couldn't read key store {}: {e}", path.display()))),
+        };
+        Ok(Self { path, keys })
+    }
+}
+impl KeyStore for FileKeyStore {
+    fn load(&self, user_id: &str) -> Result<Option<PinnedKeys>> {
+        Ok(self.keys.get(user_id).cloned())
+    }
+    fn store(&mut self, user_id: &str, keys: PinnedKeys) -> Result<()> {
+        let mut updated = self.keys.clone();
+        updated.insert(user_id.to_string(), keys);
+        // write a temporary file next to the store and move it over, so a crash can't leave a truncated store behind;
+        // the keys in memory only change once the file did, so both stay the same if writing fails
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".tmp");
+        fs::write(&temp_path, serde_json::to_string_pretty(&updated)?)
+            .and_then(|_| fs::rename(&temp_path, &self.path))
+            .map_err(|e| Errors::OtherErrors(format!("couldn't write key store {}: {e}", self.path.display())))?;
+        self.keys = updated;
+        Ok(())
+    }
+}
+
+/// Compares the contact's keys to the pinned ones, pinning them if the contact is new.
+pub fn check_keys(store: &mut impl KeyStore, contact: &ContactKeys) -> Result<KeyEvent> {
+    if contact.public_key.is_empty() {
+        return Ok(KeyEvent::NoKeys);
+    }
+    let current = PinnedKeys::from_contact(contact)?;
+    match store.load(&contact.user_id)? {
+        None => {
+            store.store(&contact.user_id, current)?;
+            Ok(KeyEvent::FirstSeen)
+        },
+        Some(pinned) if pinned.same_keys(&current) => Ok(KeyEvent::Unchanged { verified: pinned.verified }),
+        Some(pinned) => Ok(KeyEvent::KeyChanged { pinned, current }),
+    }
+}
+/// Replaces the pinned keys of a contact with its current (unverified) keys, e.g. after a [`KeyEvent::KeyChanged`].
+pub fn accept_keys(store: &mut impl KeyStore, contact: &ContactKeys) -> Result<()> {
+    store.store(&contact.user_id, PinnedKeys::from_contact(contact)?)
+}
+/// Marks the pinned encryption key of `user_id` as verified if it matches the `fingerprint`
+/// the contact told the user through another channel.
+pub fn mark_verified(store: &mut impl KeyStore, user_id: &str, fingerprint: &str) -> Result<()> {
+    let Some(mut pinned) = store.load(user_id)? else {
+        return Err(Errors::ValueError(format!("no keys pinned for user {user_id}")));
+    };
+    if normalize_fingerprint(&pinned.public_key) != normalize_fingerprint(fingerprint) {
+        return Err(Errors::ValueError(format!("fingerprint doesn't match the pinned key of user {user_id}")));
+    }
+    pinned.verified = true;
+    store.store(user_id, pinned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{private_key, CA_KEY, ENCRYPTION_KEY};
+
+    fn contact(pem: &str) -> ContactKeys {
+        ContactKeys {
+            user_id: "42".to_string(),
+            public_key: RSAPublicKey::from_key(&private_key(pem).public_key().unwrap()).to_jwk().unwrap(),
+            ..ContactKeys::default()
+        }
+    }
+
+    #[test]
+    fn contact_without_keys_isnt_pinned() {
+        let mut store = MemoryKeyStore::default();
+        let contact = ContactKeys { user_id: "42".to_string(), ..ContactKeys::default() };
+        assert_eq!(check_keys(&mut store, &contact).unwrap(), KeyEvent::NoKeys);
+        assert_eq!(store.load("42").unwrap(), None);
+    }
+    #[test]
+    fn changed_keys_are_reported() {
+        let mut store = MemoryKeyStore::default();
+        assert_eq!(check_keys(&mut store, &contact(ENCRYPTION_KEY)).unwrap(), KeyEvent::FirstSeen);
+        assert_eq!(check_keys(&mut store, &contact(ENCRYPTION_KEY)).unwrap(), KeyEvent::Unchanged { verified: false });
+        assert!(matches!(check_keys(&mut store, &contact(CA_KEY)).unwrap(), KeyEvent::KeyChanged { .. }));
+        accept_keys(&mut store, &contact(CA_KEY)).unwrap();
+        assert_eq!(check_keys(&mut store, &contact(CA_KEY)).unwrap(), KeyEvent::Unchanged { verified: false });
+    }
+    #[test]
+    fn file_store_persists_keys() {
+        let path = std::env::temp_dir().join(format!("key_store_test_{}.json", std::process::id()));
+        let mut store = FileKeyStore::open(&path).unwrap();
+        check_keys(&mut store, &contact(ENCRYPTION_KEY)).unwrap();
+        let fingerprint = store.load("42").unwrap().unwrap().public_key;
+        mark_verified(&mut store, "42", &fingerprint.to_lowercase()).unwrap();
+        let reopened = FileKeyStore::open(&path).unwrap();
+        assert_eq!(check_keys(&mut reopened.clone(), &contact(ENCRYPTION_KEY)).unwrap(), KeyEvent::Unchanged { verified: true });
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(".tmp");
+        assert!(!std::path::Path::new(&temp_path).exists());
+        fs::remove_file(path).unwrap();
+    }
+    #[test]
+    fn failed_writes_leave_the_store_unchanged() {
+        let path = std::env::temp_dir().join(format!("key_store_test_missing_{}", std::process::id())).join("keys.json");
+        let mut store = FileKeyStore::open(&path).unwrap();
+        assert!(check_keys(&mut store, &contact(ENCRYPTION_KEY)).is_err());
+        assert!(store.load("42").unwrap().is_none());
+    }
+}
@@ -1,8 +1,16 @@
+pub mod crypto;
 pub mod errors;
+pub mod geojson;
+pub mod key_ring;
+pub mod key_store;
 pub mod state;
 #[allow(dead_code, non_snake_case)]
 pub mod request_types;
 #[allow(dead_code, non_snake_case)]
 pub mod types;
 pub mod requests;
+pub mod secret;
+pub mod verification;
+#[cfg(test)]
+mod test_support;
 pub(crate) use errors::*;
\ No newline at end of file
@@ -4,11 +4,13 @@ use crate::types::user::general::UserInfo;
 use crate::types::user::others::User;
 use crate::Result;
 use base64::Engine;
-use openssl::bn::BigNum;
-use openssl::pkey::{Private, Public};
-use openssl::rsa::Rsa;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::fmt::{Debug, Formatter};
+use std::str::FromStr;
+use zeroize::Zeroize;
+use crate::crypto::{EncryptionFunction, Prf, PrivateKey, PublicKey, RsaComponents};
+use crate::secret::SecretString;
 
 #[derive(Serialize)]
 pub struct UserInfoRequest {
@@ -83,25 +85,37 @@ pub struct PrivateKeyData {
     pub deleted: Option<Value>,
     pub version: usize,
 }
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct EncryptedPrivateKeyData {
     pub iv: String,
     pub ciphertext: String,
     pub encryption_func: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub key_derivation_properties: Option<KeyDerivationProperties>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub encryptedKEK: Option<String>,
 }
-#[derive(Debug, Deserialize)]
+impl EncryptedPrivateKeyData {
+    pub fn encryption_algorithm(&self) -> Result<EncryptionFunction> {
+        self.encryption_func.parse()
+    }
+}
+#[derive(Debug, Serialize, Deserialize)]
 pub struct KeyDerivationProperties {
     pub prf: String,
     pub iterations: usize,
     pub salt: String,
 }
+impl KeyDerivationProperties {
+    pub fn prf_algorithm(&self) -> Result<Prf> {
+        self.prf.parse()
+    }
+}
 #[derive(Debug, Deserialize)]
 pub struct PemPrivateKey {
     pub private: String
 }
-#[derive(Debug, Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct RSAPrivateKey {
     pub n: String,
     pub e: String,
@@ -112,63 +126,141 @@ pub struct RSAPrivateKey {
     pub dq: String,
     pub qi: String,
 }
-impl From<RSAPrivateKey> for Result<Rsa<Private>> {
+/// Only the public components are shown.
+impl Debug for RSAPrivateKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RSAPrivateKey")
+            .field("n", &self.n)
+            .field("e", &self.e)
+            .finish_non_exhaustive()
+    }
+}
+impl Drop for RSAPrivateKey {
+    fn drop(&mut self) {
+        self.d.zeroize();
+        self.p.zeroize();
+        self.q.zeroize();
+        self.dp.zeroize();
+        self.dq.zeroize();
+        self.qi.zeroize();
+    }
+}
+impl From<RSAPrivateKey> for Result<PrivateKey> {
     fn from(value: RSAPrivateKey) -> Self {
         (&value).into()
     }
 }
-impl From<&RSAPrivateKey> for Result<Rsa<Private>> {
+impl From<&RSAPrivateKey> for Result<PrivateKey> {
     fn from(value: &RSAPrivateKey) -> Self {
-        Ok(openssl::rsa::RsaPrivateKeyBuilder::new(
-            to_big_num(value.n.clone())?,
-            to_big_num(value.e.clone())?,
-            to_big_num(value.d.clone())?,
-        )?.set_factors(
-            to_big_num(value.p.clone())?,
-            to_big_num(value.q.clone())?,
-        )?.build())
+        PrivateKey::from_components(&RsaComponents {
+            n: to_big_num(&value.n)?,
+            e: to_big_num(&value.e)?,
+            d: to_big_num(&value.d)?,
+            p: to_big_num(&value.p)?,
+            q: to_big_num(&value.q)?,
+            dp: to_big_num(&value.dp)?,
+            dq: to_big_num(&value.dq)?,
+            qi: to_big_num(&value.qi)?,
+        })
     }
 }
-impl RSAPrivateKey {
-    pub fn from_str(private_key: &str) -> Result<RSAPrivateKey> {
-        serde_json::from_str(private_key).map_err(Errors::from)
+impl FromStr for RSAPrivateKey {
+    type Err = Errors;
+    fn from_str(private_key: &str) -> Result<RSAPrivateKey> {
+        Ok(serde_json::from_str(private_key)?)
     }
+}
+impl RSAPrivateKey {
     pub fn from_decrypted(private_key: Vec<u8>) -> Result<RSAPrivateKey> {
-        Self::from_str(&*String::from_utf8(private_key)?)
+        let private_key = SecretString::from(String::from_utf8(private_key)?);
+        Self::from_str(private_key.expose())
     }
-    pub fn to_key(&self) -> Result<Rsa<Private>> {
+    pub fn to_key(&self) -> Result<PrivateKey> {
         self.into()
     }
+    pub fn from_key(key: &PrivateKey) -> Result<RSAPrivateKey> {
+        let components = key.components()?;
+        Ok(Self {
+            n: from_big_num(&components.n),
+            e: from_big_num(&components.e),
+            d: from_big_num(&components.d),
+            p: from_big_num(&components.p),
+            q: from_big_num(&components.q),
+            dp: from_big_num(&components.dp),
+            dq: from_big_num(&components.dq),
+            qi: from_big_num(&components.qi),
+        })
+    }
+    /// JSON Web Key document of the private key.
+    pub fn to_jwk(&self) -> Result<SecretString> {
+        Ok(serde_json::to_string(&Jwk { kty: "RSA", key: self })?.into())
+    }
+    /// PKCS#8 PEM document of the private key.
+    pub fn to_pem(&self) -> Result<SecretString> {
+        self.to_key()?.to_pem()
+    }
+    /// Reads a PKCS#8 or PKCS#1 PEM document.
+    pub fn from_pem(pem: &str) -> Result<RSAPrivateKey> {
+        Self::from_key(&PrivateKey::from_pem(pem)?)
+    }
 }
-fn to_big_num(string: String) -> Result<BigNum> {
-    BigNum::from_slice(&*base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(string)
-        .map_err(|e| Errors::Base64Error(e))?)
-        .map_err(|e| Errors::EncryptionError(e))
+/// Adds the key type to serialized keys, making them standard JSON Web Keys.
+#[derive(Serialize)]
+struct Jwk<'a, T: Serialize> {
+    kty: &'static str,
+    #[serde(flatten)]
+    key: &'a T,
 }
-#[derive(Debug, Deserialize)]
+fn from_big_num(number: &[u8]) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(number)
+}
+fn to_big_num(string: &str) -> Result<Vec<u8>> {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(string)
+        .map_err(Errors::Base64Error)
+}
+#[derive(Debug, Serialize, Deserialize)]
 pub struct RSAPublicKey {
     pub n: String,
     pub e: String,
 }
-impl RSAPublicKey {
-    pub fn from_str(public_key: &str) -> Result<RSAPublicKey> {
-        serde_json::from_str(public_key).map_err(|e| Errors::from(e))
+impl FromStr for RSAPublicKey {
+    type Err = Errors;
+    fn from_str(public_key: &str) -> Result<RSAPublicKey> {
+        Ok(serde_json::from_str(public_key)?)
     }
-    pub fn to_key(&self) -> Result<Rsa<Public>> {
+}
+impl RSAPublicKey {
+    pub fn to_key(&self) -> Result<PublicKey> {
         self.into()
     }
+    pub fn from_key(key: &PublicKey) -> RSAPublicKey {
+        let (n, e) = key.components();
+        Self {
+            n: from_big_num(&n),
+            e: from_big_num(&e),
+        }
+    }
+    /// JSON Web Key document of the public key.
+    pub fn to_jwk(&self) -> Result<String> {
+        Ok(serde_json::to_string(&Jwk { kty: "RSA", key: self })?)
+    }
+    /// SubjectPublicKeyInfo PEM document of the public key.
+    pub fn to_pem(&self) -> Result<String> {
+        self.to_key()?.to_pem()
+    }
+    /// Reads a SubjectPublicKeyInfo or PKCS#1 PEM document.
+    pub fn from_pem(pem: &str) -> Result<RSAPublicKey> {
+        Ok(Self::from_key(&PublicKey::from_pem(pem)?))
+    }
 }
-impl From<&RSAPublicKey> for Result<Rsa<Public>> {
+impl From<&RSAPublicKey> for Result<PublicKey> {
     fn from(value: &RSAPublicKey) -> Self {
-        openssl::rsa::Rsa::from_public_components(
-            to_big_num(value.n.clone())?,
-            to_big_num(value.e.clone())?,
-        ).map_err(|e| Errors::from(e))
+        PublicKey::from_components(&to_big_num(&value.n)?, &to_big_num(&value.e)?)
     }
 }
-impl From<RSAPublicKey> for Result<Rsa<Public>> {
+impl From<RSAPublicKey> for Result<PublicKey> {
     fn from(value: RSAPublicKey) -> Self {
-        (&value).to_key()
+        value.to_key()
     }
 }
 
@@ -193,6 +285,26 @@ impl OtherUserInfoRequest {
 pub struct OtherUserInfoResponse {
     pub user: User,
 }
+#[derive(Serialize)]
+pub struct CompanyCARequest {
+    client_key: String,
+    device_id: String,
+    company_id: String,
+}
+impl CompanyCARequest {
+    pub fn new(state: &State, company_id: String) -> Result<Self> {
+        Ok(Self {
+            client_key: state.expect_client_key()?,
+            device_id: state.get_device_id(),
+            company_id,
+        })
+    }
+}
+#[derive(Debug, Deserialize)]
+pub struct CompanyCAResponse {
+    /// JWK encoded public key of the company CA.
+    pub public_key: String,
+}
 #[derive(Deserialize)]
 pub struct PublicSigningKey {
     pub alg: String,
@@ -3,7 +3,9 @@ use crate::state::State;
 use crate::Result;
 use crate::types::chats::channels::Channel;
 use crate::types::chats::conversations::Conversation;
-use crate::types::chats::messages::Message;
+use crate::types::chats::messages::{File, Message, MessageReplyTo};
+use crate::types::DetailedPersonInfo;
+use crate::verification::SignedContent;
 
 #[derive(Serialize)]
 pub struct ConversationsRequest {
@@ -51,6 +53,30 @@ pub struct ChannelsResponse {
     pub channels: Vec<Channel>,
 }
 
+#[derive(Serialize)]
+pub struct ChannelMembersRequest {
+    pub client_key: String,
+    pub device_id: String,
+    pub channel_id: String,
+    pub limit: String,
+    pub offset: String,
+}
+impl ChannelMembersRequest {
+    pub fn new(state: &State, channel_id: String, limit: usize, offset: usize) -> Result<Self> {
+        Ok(Self {
+            client_key: state.expect_client_key()?,
+            device_id: state.get_device_id(),
+            channel_id,
+            limit: limit.to_string(),
+            offset: offset.to_string(),
+        })
+    }
+}
+#[derive(Deserialize, Debug)]
+pub struct ChannelMembersResponse {
+    pub members: Vec<DetailedPersonInfo>,
+}
+
 #[derive(Serialize, Debug)]
 pub struct MessageRequest {
     pub client_key: String,
@@ -84,6 +110,61 @@ pub struct FileDownloadQuery {
     pub id: String,
 }
 
+/// Optional fields of a message sent with [`send_message`](crate::requests::chats::send_message).
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct SendMessageOptions {
+    /// `"text"` for normal messages.
+    pub r#type: String,
+    pub is_forwarded: bool,
+    pub metainfo: Option<String>,
+    /// Whether [`send_message`](crate::requests::chats::send_message) and
+    /// [`send_chat_message`](crate::requests::chats::send_chat_message) may send plain text into an encrypted chat
+    /// if no [`EncryptionState`](crate::state::EncryptionState) is given,
+    /// instead of failing with [`Errors::EncryptionRequired`](crate::errors::Errors::EncryptionRequired).
+    pub send_unencrypted_into_encrypted_chats: bool,
+    /// The message this one answers, see [`reply_to`](crate::requests::chats::reply_to).
+    pub reply_to: Option<MessageReplyTo>,
+    /// Asks the members to confirm they read the message, see [`get_confirmation_report`](crate::requests::messages::get_confirmation_report).
+    pub confirmation_required: bool,
+}
+impl Default for SendMessageOptions {
+    fn default() -> Self {
+        Self {
+            r#type: "text".to_string(),
+            is_forwarded: false,
+            metainfo: None,
+            send_unencrypted_into_encrypted_chats: false,
+            reply_to: None,
+            confirmation_required: false,
+        }
+    }
+}
+/// Message content as it is sent: hex encoded ciphertext and IV for encrypted chats, signed with the `experimental` feature.
+pub(crate) struct PreparedText {
+    pub(crate) text: String,
+    pub(crate) iv: Option<String>,
+    pub(crate) encrypted: bool,
+    pub(crate) verification: String,
+    pub(crate) files: Vec<String>,
+    pub(crate) latitude: Option<String>,
+    pub(crate) longitude: Option<String>,
+    pub(crate) metainfo: Option<String>,
+}
+impl PreparedText {
+    /// `text` and `content` as they are, neither encrypted nor signed.
+    pub(crate) fn plain(text: &str, content: SignedContent, metainfo: Option<String>) -> Self {
+        Self {
+            text: text.to_string(),
+            iv: None,
+            encrypted: false,
+            verification: String::new(),
+            files: content.files,
+            latitude: content.latitude,
+            longitude: content.longitude,
+            metainfo,
+        }
+    }
+}
 #[derive(Serialize)]
 pub struct SendMessageRequest {
     pub client_key: String,
@@ -100,26 +181,126 @@ pub struct SendMessageRequest {
     pub verification: String,
     pub r#type: String,
     pub is_forwarded: String,
+    pub confirmation_required: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metainfo: Option<String>,
+    /// JSON encoded [`MessageReplyTo`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
+    /// Degrees, hex encoded ciphertext in encrypted chats.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<String>,
 }
 impl SendMessageRequest {
-    pub fn new(state: &State, target: String, chat_id: String, text: String, files: Vec<String>, url: Vec<Option<String>>, encrypted: bool, iv: Option<String>, verification: String, r#type: String, is_forwarded: bool, metainfo: Option<String>) -> Result<SendMessageRequest> {
+    /// `prepared` holds the content as it is sent (see [`prepare_text`](crate::requests::chats::prepare_text)),
+    /// `options` the remaining fields.
+    pub(crate) fn new(state: &State, target: String, chat_id: String, prepared: PreparedText, options: SendMessageOptions) -> Result<SendMessageRequest> {
         Ok(Self {
             client_key: state.expect_client_key()?,
             device_id: state.get_device_id(),
             target,
             conversation_id: chat_id.clone(),
             channel_id: chat_id,
-            text,
-            files: format!("[{}]", files.join(",")),
-            url: format!("[{}]", vec!["null"; url.len()].join(",")), // TODO
-            encrypted: encrypted.to_string(),
-            iv,
-            verification,
+            text: prepared.text,
+            files: format!("[{}]", prepared.files.join(",")),
+            url: format!("[{}]", vec!["null"; prepared.files.len()].join(",")), // TODO
+            encrypted: prepared.encrypted.to_string(),
+            iv: prepared.iv,
+            verification: prepared.verification,
+            r#type: options.r#type,
+            is_forwarded: options.is_forwarded.to_string(),
+            confirmation_required: options.confirmation_required.to_string(),
+            metainfo: prepared.metainfo,
+            reply_to: match &options.reply_to {
+                Some(reply_to) => Some(serde_json::to_string(reply_to)?),
+                None => None,
+            },
+            latitude: prepared.latitude,
+            longitude: prepared.longitude,
+        })
+    }
+}
+#[derive(Debug, Deserialize)]
+pub struct SendMessageResponse {
+    pub message: Message,
+}
+
+#[derive(Serialize)]
+pub struct ThreadRequest {
+    pub client_key: String,
+    pub device_id: String,
+    pub thread_id: String,
+    pub limit: String,
+    pub offset: String,
+}
+impl ThreadRequest {
+    pub fn new(state: &State, thread_id: String, limit: usize, offset: usize) -> Result<Self> {
+        Ok(Self {
+            client_key: state.expect_client_key()?,
+            device_id: state.get_device_id(),
+            thread_id,
+            limit: limit.to_string(),
+            offset: offset.to_string(),
+        })
+    }
+}
+
+/// Text fields of the `multipart/form-data` upload, the file itself is added as the `file` part.
+pub struct FileUploadRequest {
+    pub client_key: String,
+    pub device_id: String,
+    pub r#type: String,
+    pub type_id: String,
+    pub encrypted: bool,
+    pub e2e_iv: Option<String>,
+}
+impl FileUploadRequest {
+    pub fn new(state: &State, r#type: String, type_id: String, encrypted: bool, e2e_iv: Option<String>) -> Result<Self> {
+        Ok(Self {
+            client_key: state.expect_client_key()?,
+            device_id: state.get_device_id(),
             r#type,
-            is_forwarded: is_forwarded.to_string(),
-            metainfo,
+            type_id,
+            encrypted,
+            e2e_iv,
+        })
+    }
+    pub fn fields(self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("client_key", self.client_key),
+            ("device_id", self.device_id),
+            ("type", self.r#type),
+            ("type_id", self.type_id),
+            ("encrypted", self.encrypted.to_string()),
+        ];
+        if let Some(iv) = self.e2e_iv {
+            fields.push(("e2e_iv", iv));
+        }
+        fields
+    }
+}
+#[derive(Serialize)]
+pub struct ShareFileRequest {
+    pub client_key: String,
+    pub device_id: String,
+    pub file_id: String,
+    pub target: String,
+    pub target_id: String,
+}
+impl ShareFileRequest {
+    pub fn new(state: &State, file_id: String, target: String, target_id: String) -> Result<Self> {
+        Ok(Self {
+            client_key: state.expect_client_key()?,
+            device_id: state.get_device_id(),
+            file_id,
+            target,
+            target_id,
         })
     }
-}
\ No newline at end of file
+}
+#[derive(Debug, Deserialize)]
+pub struct FileResponse {
+    pub file: File,
+}
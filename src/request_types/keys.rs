@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+use crate::state::State;
+use crate::types::DetailedPersonInfo;
+use crate::Result;
+
+/// Request that only names a chat, used by several `/security` endpoints.
+#[derive(Serialize)]
+pub struct KeyTargetRequest {
+    pub client_key: String,
+    pub device_id: String,
+    pub r#type: String,
+    pub type_id: String,
+}
+impl KeyTargetRequest {
+    pub fn new(state: &State, r#type: String, type_id: String) -> Result<Self> {
+        Ok(Self {
+            client_key: state.expect_client_key()?,
+            device_id: state.get_device_id(),
+            r#type,
+            type_id,
+        })
+    }
+}
+#[derive(Deserialize, Debug)]
+pub struct MembersWithoutKeysResponse {
+    pub users: Vec<DetailedPersonInfo>,
+}
+
+#[derive(Serialize)]
+pub struct SetMissingKeyRequest {
+    pub client_key: String,
+    pub device_id: String,
+    pub r#type: String,
+    pub type_id: String,
+    pub user_id: String,
+    pub key: String,
+    pub key_signature: String,
+}
+impl SetMissingKeyRequest {
+    pub fn new(state: &State, r#type: String, type_id: String, user_id: String, key: String, key_signature: String) -> Result<Self> {
+        Ok(Self {
+            client_key: state.expect_client_key()?,
+            device_id: state.get_device_id(),
+            r#type,
+            type_id,
+            user_id,
+            key,
+            key_signature,
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct KeyRequestsResponse {
+    pub requests: Vec<KeyRequestData>,
+}
+#[derive(Deserialize, Debug)]
+pub struct KeyRequestData {
+    pub user: DetailedPersonInfo,
+    pub time: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct RejectKeyRequest {
+    pub client_key: String,
+    pub device_id: String,
+    pub r#type: String,
+    pub type_id: String,
+    pub user_id: String,
+}
+impl RejectKeyRequest {
+    pub fn new(state: &State, r#type: String, type_id: String, user_id: String) -> Result<Self> {
+        Ok(Self {
+            client_key: state.expect_client_key()?,
+            device_id: state.get_device_id(),
+            r#type,
+            type_id,
+            user_id,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct RotateKeyRequest {
+    pub client_key: String,
+    pub device_id: String,
+    pub r#type: String,
+    pub type_id: String,
+    /// JSON list of [`MemberKey`]s.
+    pub keys: String,
+}
+impl RotateKeyRequest {
+    pub fn new(state: &State, r#type: String, type_id: String, keys: &[MemberKey]) -> Result<Self> {
+        Ok(Self {
+            client_key: state.expect_client_key()?,
+            device_id: state.get_device_id(),
+            r#type,
+            type_id,
+            keys: serde_json::to_string(keys)?,
+        })
+    }
+}
+#[derive(Serialize, Debug)]
+pub struct MemberKey {
+    pub user_id: String,
+    pub key: String,
+    pub key_signature: String,
+}
@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::state::State;
+use crate::types::chats::messages::{Message, MessageSeen};
+use crate::types::DetailedPersonInfo;
+use crate::Result;
+
+/// Request that only names a message, used by several `/message` endpoints.
+#[derive(Serialize)]
+pub struct MessageIdRequest {
+    pub client_key: String,
+    pub device_id: String,
+    pub message_id: String,
+}
+impl MessageIdRequest {
+    pub fn new(state: &State, message_id: String) -> Result<Self> {
+        Ok(Self {
+            client_key: state.expect_client_key()?,
+            device_id: state.get_device_id(),
+            message_id,
+        })
+    }
+}
+/// Response of endpoints changing a message. Some of them don't echo the message.
+#[derive(Deserialize, Debug)]
+pub struct MessageChangeResponse {
+    #[serde(default)]
+    pub message: Option<Message>,
+}
+
+#[derive(Serialize)]
+pub struct EditMessageRequest {
+    pub client_key: String,
+    pub device_id: String,
+    pub message_id: String,
+    pub text: String,
+    pub encrypted: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iv: Option<String>,
+    pub verification: String,
+}
+impl EditMessageRequest {
+    pub fn new(state: &State, message_id: String, text: String, encrypted: bool, iv: Option<String>, verification: String) -> Result<Self> {
+        Ok(Self {
+            client_key: state.expect_client_key()?,
+            device_id: state.get_device_id(),
+            message_id,
+            text,
+            encrypted: encrypted.to_string(),
+            iv,
+            verification,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct FlaggedMessagesRequest {
+    pub client_key: String,
+    pub device_id: String,
+    pub limit: String,
+    pub offset: String,
+}
+impl FlaggedMessagesRequest {
+    pub fn new(state: &State, limit: usize, offset: usize) -> Result<Self> {
+        Ok(Self {
+            client_key: state.expect_client_key()?,
+            device_id: state.get_device_id(),
+            limit: limit.to_string(),
+            offset: offset.to_string(),
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct MarkReadRequest {
+    pub client_key: String,
+    pub device_id: String,
+    pub r#type: String,
+    pub type_id: String,
+    /// Everything up to and including this message is marked read, the whole chat if `None`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+}
+impl MarkReadRequest {
+    pub fn new(state: &State, r#type: String, type_id: String, message_id: Option<String>) -> Result<Self> {
+        Ok(Self {
+            client_key: state.expect_client_key()?,
+            device_id: state.get_device_id(),
+            r#type,
+            type_id,
+            message_id,
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ConfirmationsResponse {
+    pub confirmations: Vec<ConfirmationData>,
+}
+#[derive(Deserialize, Debug)]
+pub struct ConfirmationData {
+    pub user: DetailedPersonInfo,
+    /// Unix timestamp, as a number or a string.
+    pub time: Value,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SeenResponse {
+    pub seen: Vec<MessageSeen>,
+}
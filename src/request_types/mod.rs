@@ -6,6 +6,8 @@ use crate::Result;
 pub(crate) mod login;
 pub(crate) mod about;
 pub(crate) mod chats;
+pub(crate) mod keys;
+pub(crate) mod messages;
 
 #[derive(Deserialize, Debug)]
 pub(crate) struct APIResponseStatus {
@@ -0,0 +1,137 @@
+use crate::crypto::PublicKey;
+use serde_json::Value;
+use crate::request_types::keys;
+use crate::requests::blocking::about::{get_other_user_info, get_user_info};
+use crate::requests::blocking::chats::{get_all_chats, get_channel_members};
+use crate::requests::blocking::post_request;
+use crate::key_ring::ChatKeyRing;
+use crate::requests::keys::{check_rotation_keys, unix_time, wrap_chat_key, wrap_own_chat_key};
+pub use crate::requests::keys::{KeyDistributionStatus, KeyRequest, MemberKeyReport};
+use crate::secret::SecretBytes;
+use crate::state::{EncryptionState, State};
+use crate::types::chats::{AnyChat, Chat};
+use crate::types::DetailedPersonInfo;
+use crate::verification::{ContactKeys, TrustPolicy};
+use crate::Result;
+
+pub fn get_members_without_keys(state: &State, chat: &impl Chat) -> Result<Vec<DetailedPersonInfo>> {
+    Ok(post_request::<keys::MembersWithoutKeysResponse>(state,
+                                                        "/security/members_without_keys",
+                                                        keys::KeyTargetRequest::new(state, chat.chat_type().to_string(), chat.id().to_string())?)?
+        .users)
+}
+/// Encrypts the chat key for a single member and uploads it.
+pub fn send_chat_key(state: &State, encryption_state: &EncryptionState, chat: &impl Chat, chat_key: &SecretBytes, user_id: String, ca_key: &PublicKey, policy: TrustPolicy) -> Result<()> {
+    let user = get_other_user_info(state, user_id.clone())?;
+    let wrapped = wrap_chat_key(encryption_state, &ContactKeys::from(&user), chat_key, ca_key, policy)?;
+    post_request::<Value>(state,
+                          "/security/set_missing_key",
+                          keys::SetMissingKeyRequest::new(state, chat.chat_type().to_string(), chat.id().to_string(), user_id, wrapped.key, wrapped.signature)?)?;
+    Ok(())
+}
+/// Sends the chat key to every member that doesn't have it yet.
+/// Keys of members that don't satisfy `policy` are skipped, see [`ContactKeys::verify`].
+pub fn distribute_chat_key(state: &State, encryption_state: &EncryptionState, chat: &impl Chat, ca_key: &PublicKey, policy: TrustPolicy) -> Result<Vec<MemberKeyReport>> {
+    if chat.num_members_without_keys() == 0 {
+        return Ok(vec![]);
+    }
+    let chat_key = encryption_state.chat_key(chat)?;
+    let mut reports = vec![];
+    for member in get_members_without_keys(state, chat)? {
+        let Some(user_id) = member.id else {
+            continue;
+        };
+        let status = match send_chat_key(state, encryption_state, chat, &chat_key, user_id.clone(), ca_key, policy) {
+            Ok(()) => KeyDistributionStatus::Sent,
+            Err(e) => e.into(),
+        };
+        reports.push(MemberKeyReport { user_id, status });
+    }
+    Ok(reports)
+}
+/// Lists the key requests for a single chat.
+pub fn get_key_requests(state: &State, chat: &AnyChat, ca_key: &PublicKey) -> Result<Vec<KeyRequest>> {
+    let requests = post_request::<keys::KeyRequestsResponse>(state,
+                                                             "/security/get_key_requests",
+                                                             keys::KeyTargetRequest::new(state, chat.chat_type().to_string(), chat.id().to_string())?)?
+        .requests;
+    let mut result = vec![];
+    for request in requests {
+        let Some(user_id) = request.user.id else {
+            continue;
+        };
+        let requester = get_other_user_info(state, user_id)?;
+        result.push(KeyRequest {
+            chat: chat.clone(),
+            trust: ContactKeys::from(&requester).verify(ca_key)?,
+            requester,
+            time: request.time,
+        });
+    }
+    Ok(result)
+}
+/// Lists the pending key requests of all chats we hold the key of.
+pub fn get_pending_key_requests(state: &State, ca_key: &PublicKey) -> Result<Vec<KeyRequest>> {
+    let mut requests = vec![];
+    for chat in get_all_chats(state)? {
+        if chat.key_requested() && chat.key().is_some() {
+            requests.extend(get_key_requests(state, &chat, ca_key)?);
+        }
+    }
+    Ok(requests)
+}
+/// Answers a key request by encrypting the chat key for the requester and sending it.
+pub fn approve_key_request(state: &State, encryption_state: &EncryptionState, request: &KeyRequest, ca_key: &PublicKey, policy: TrustPolicy) -> Result<()> {
+    let chat_key = encryption_state.chat_key(&request.chat)?;
+    send_chat_key(state, encryption_state, &request.chat, &chat_key, request.requester.id.clone(), ca_key, policy)
+}
+pub fn reject_key_request(state: &State, request: &KeyRequest) -> Result<()> {
+    post_request::<Value>(state,
+                          "/security/reject_key_request",
+                          keys::RejectKeyRequest::new(state, request.chat.chat_type().to_string(), request.chat.id().to_string(), request.requester.id.clone())?)?;
+    Ok(())
+}
+/// Replaces the chat key with a new random key for the chat's cipher, encrypted for every current member, e.g. after someone left.
+///
+/// The previous and the new key are added to `key_ring`, so older messages stay readable (see `get_chat_messages`).
+/// Members whose keys don't satisfy `policy` don't receive the new key and are reported as such.
+/// Our own copy is encrypted with our own key without checking it against the CA.
+/// Nothing is rotated unless the new key can be encrypted for us and for at least one other member.
+pub fn rotate_chat_key(state: &State, encryption_state: &EncryptionState, chat: &impl Chat, key_ring: &mut ChatKeyRing, ca_key: &PublicKey, policy: TrustPolicy) -> Result<Vec<MemberKeyReport>> {
+    if !key_ring.contains_chat(chat) {
+        if let Ok(previous) = encryption_state.chat_key(chat) {
+            key_ring.add(encryption_state, chat, &previous, 0)?;
+        }
+    }
+    let mut new_key = SecretBytes::zeroed(chat.cipher()?.key_len());
+    crate::crypto::random_bytes(new_key.expose_mut())?;
+    let own_id = get_user_info(state)?.id;
+
+    let members = match chat.known_members() {
+        Some(members) => members.to_vec(),
+        None => get_channel_members(state, chat.id())?,
+    };
+    let mut keys = vec![];
+    let mut reports = vec![];
+    for user_id in members.into_iter().filter_map(|member| member.id) {
+        let wrapped = if user_id == own_id {
+            wrap_own_chat_key(encryption_state, &new_key)
+        } else {
+            match get_other_user_info(state, user_id.clone()) {
+                Ok(user) => wrap_chat_key(encryption_state, &ContactKeys::from(&user), &new_key, ca_key, policy),
+                Err(e) => Err(e),
+            }
+        };
+        match wrapped {
+            Ok(wrapped) => keys.push(keys::MemberKey { user_id, key: wrapped.key, key_signature: wrapped.signature }),
+            Err(e) => reports.push(MemberKeyReport { user_id, status: e.into() }),
+        }
+    }
+    check_rotation_keys(&own_id, &keys)?;
+    post_request::<Value>(state,
+                          "/security/rotate_key",
+                          keys::RotateKeyRequest::new(state, chat.chat_type().to_string(), chat.id().to_string(), &keys)?)?;
+    key_ring.add(encryption_state, chat, &new_key, unix_time())?;
+    reports.extend(keys.into_iter().map(|key| MemberKeyReport { user_id: key.user_id, status: KeyDistributionStatus::Sent }));
+    Ok(reports)
+}
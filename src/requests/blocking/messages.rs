@@ -0,0 +1,202 @@
+use serde_json::Value;
+use crate::errors::Errors;
+use crate::key_ring::ChatKeyRing;
+use crate::request_types::{chats, messages};
+use crate::requests::blocking::about::get_user_info;
+use crate::requests::blocking::chats::{download_file, get_all_chats, get_channel_members, share_file, upload_file};
+use crate::requests::blocking::post_request;
+pub use crate::requests::messages::{may_delete_messages, ForwardOptions, unseen_members, ConfirmationReport, MemberConfirmation};
+use crate::requests::messages::{confirmation_report, decrypt_if_possible, deleted_message, edited_message, flagged_message, liked_message, prepare_edit, prepare_forward, prepare_forwarded_text};
+use crate::state::{EncryptionState, State};
+use crate::types::chats::channels::Channel;
+use crate::types::chats::messages::{Message, MessageSeen};
+use crate::types::chats::{AnyChat, Chat};
+use crate::types::user::companies::CompanySettings;
+use crate::types::DetailedPersonInfo;
+use crate::Result;
+
+const PAGE_SIZE: usize = 100;
+
+/// Replaces the text of one of our own messages in `chat`, re-encrypting it in encrypted chats.
+/// Returns the edited message, decrypted again.
+pub fn edit_message(state: &State, chat: &impl Chat, encryption_state: Option<&EncryptionState>, message: &Message, text: impl ToString) -> Result<Message> {
+    let own_id = get_user_info(state)?.id;
+    let prepared = prepare_edit(chat, encryption_state, message, &text.to_string(), &own_id)?;
+    let echoed = post_request::<messages::MessageChangeResponse>(state,
+                                                                 "/message/edit",
+                                                                 messages::EditMessageRequest::new(state, message.id.to_string(), prepared.text.clone(), prepared.encrypted, prepared.iv.clone(), prepared.verification.clone())?)?
+        .message;
+    edited_message(chat, encryption_state, message, echoed, prepared)
+}
+/// Deletes `message` from `chat` if [`may_delete_messages`] allows it to us under the `settings` of the chat's company.
+/// Returns the message with its `deleted` timestamp set.
+pub fn delete_message(state: &State, chat: &AnyChat, settings: &CompanySettings, message: &Message) -> Result<Message> {
+    let own_id = get_user_info(state)?.id;
+    if !may_delete_messages(chat, settings, message, &own_id) {
+        return Err(Errors::PermissionDenied(format!("deleting message {} in chat {}", message.id, chat.id())));
+    }
+    let echoed = post_request::<messages::MessageChangeResponse>(state,
+                                                                 "/message/delete",
+                                                                 messages::MessageIdRequest::new(state, message.id.to_string())?)?
+        .message;
+    Ok(deleted_message(message, echoed))
+}
+/// Likes `message` and returns it with the updated like count.
+pub fn like_message(state: &State, message: &Message) -> Result<Message> {
+    let echoed = post_request::<messages::MessageChangeResponse>(state,
+                                                                 "/message/like",
+                                                                 messages::MessageIdRequest::new(state, message.id.to_string())?)?
+        .message;
+    Ok(liked_message(message, echoed, true))
+}
+/// Takes back a like of `message` and returns it with the updated like count.
+pub fn unlike_message(state: &State, message: &Message) -> Result<Message> {
+    let echoed = post_request::<messages::MessageChangeResponse>(state,
+                                                                 "/message/unlike",
+                                                                 messages::MessageIdRequest::new(state, message.id.to_string())?)?
+        .message;
+    Ok(liked_message(message, echoed, false))
+}
+/// Flags `message` for ourselves.
+pub fn flag_message(state: &State, message: &Message) -> Result<Message> {
+    let echoed = post_request::<messages::MessageChangeResponse>(state,
+                                                                 "/message/set_flag",
+                                                                 messages::MessageIdRequest::new(state, message.id.to_string())?)?
+        .message;
+    Ok(flagged_message(message, echoed, true))
+}
+/// Removes our flag from `message`.
+pub fn unflag_message(state: &State, message: &Message) -> Result<Message> {
+    let echoed = post_request::<messages::MessageChangeResponse>(state,
+                                                                 "/message/unset_flag",
+                                                                 messages::MessageIdRequest::new(state, message.id.to_string())?)?
+        .message;
+    Ok(flagged_message(message, echoed, false))
+}
+/// Fetches the messages we flagged in all chats, page by page.
+/// With an `encryption_state`, messages are decrypted with the key of their chat (and older keys from `key_ring`)
+/// where one is available; the others are returned encrypted.
+pub fn get_flagged_messages(state: &State, encryption_state: Option<&EncryptionState>, key_ring: Option<&ChatKeyRing>) -> Result<Vec<Message>> {
+    let mut flagged = vec![];
+    loop {
+        let page = post_request::<chats::MessageResponse>(state,
+                                                          "/message/list_flagged",
+                                                          messages::FlaggedMessagesRequest::new(state, PAGE_SIZE, flagged.len())?)?
+            .messages;
+        let done = page.len() < PAGE_SIZE;
+        flagged.extend(page);
+        if done {
+            break;
+        }
+    }
+    for message in &mut flagged {
+        message.keep_originals();
+    }
+    let Some(encryption_state) = encryption_state else {
+        return Ok(flagged);
+    };
+    let chats = get_all_chats(state)?;
+    for message in &mut flagged {
+        decrypt_if_possible(message, &chats, encryption_state, key_ring);
+    }
+    Ok(flagged)
+}
+/// Marks a single message as read.
+pub fn mark_message_read(state: &State, message: &Message) -> Result<()> {
+    post_request::<Value>(state,
+                          "/message/mark_read",
+                          messages::MessageIdRequest::new(state, message.id.to_string())?)?;
+    Ok(())
+}
+/// Marks `message` and every message before it in `chat` as read.
+pub fn mark_read_until(state: &State, chat: &impl Chat, message: &Message) -> Result<()> {
+    post_request::<Value>(state,
+                          "/message/mark_chat_read",
+                          messages::MarkReadRequest::new(state, chat.chat_type().to_string(), chat.id().to_string(), Some(message.id.to_string()))?)?;
+    Ok(())
+}
+/// Marks every message in `chat` as read.
+pub fn mark_chat_read(state: &State, chat: &impl Chat) -> Result<()> {
+    post_request::<Value>(state,
+                          "/message/mark_chat_read",
+                          messages::MarkReadRequest::new(state, chat.chat_type().to_string(), chat.id().to_string(), None)?)?;
+    Ok(())
+}
+/// Marks all channels of every company and all conversations with unread messages as read.
+/// Returns how many chats have been marked.
+pub fn mark_all_read(state: &State) -> Result<usize> {
+    let mut marked = 0;
+    for chat in get_all_chats(state)?.iter().filter(|chat| chat.unread() > 0) {
+        mark_chat_read(state, chat)?;
+        marked += 1;
+    }
+    Ok(marked)
+}
+/// The members of `chat`, fetched for channels and taken from the chat object for conversations.
+pub fn get_chat_members(state: &State, chat: &impl Chat) -> Result<Vec<DetailedPersonInfo>> {
+    match chat.known_members() {
+        Some(members) => Ok(members.to_vec()),
+        None => get_channel_members(state, chat.id()),
+    }
+}
+/// Confirms that we read `message`, which requires confirmation.
+pub fn confirm_message(state: &State, message: &Message) -> Result<Message> {
+    let echoed = post_request::<messages::MessageChangeResponse>(state,
+                                                                 "/message/confirm",
+                                                                 messages::MessageIdRequest::new(state, message.id.to_string())?)?
+        .message;
+    Ok(echoed.unwrap_or_else(|| message.clone()))
+}
+/// Lists who confirmed `message` in `chat` and when, as well as the members who haven't confirmed it yet.
+pub fn get_confirmation_report(state: &State, chat: &impl Chat, message: &Message) -> Result<ConfirmationReport> {
+    let confirmations = post_request::<messages::ConfirmationsResponse>(state,
+                                                                        "/message/list_confirmations",
+                                                                        messages::MessageIdRequest::new(state, message.id.to_string())?)?
+        .confirmations;
+    let members = get_chat_members(state, chat)?;
+    Ok(confirmation_report(confirmations, members, message))
+}
+/// Fetches everyone who has seen `message`, oldest first.
+pub fn get_seen_by(state: &State, message: &Message) -> Result<Vec<MessageSeen>> {
+    let mut seen = post_request::<messages::SeenResponse>(state,
+                                                          "/message/list_seen",
+                                                          messages::MessageIdRequest::new(state, message.id.to_string())?)?
+        .seen;
+    seen.sort_by_key(|seen| seen.time);
+    Ok(seen)
+}
+/// The members of `channel` who haven't seen `message` yet.
+pub fn get_unseen_members(state: &State, channel: &Channel, message: &Message) -> Result<Vec<DetailedPersonInfo>> {
+    let seen = get_seen_by(state, message)?;
+    let members = get_channel_members(state, channel.id())?;
+    Ok(unseen_members(members, &seen, message))
+}
+/// Forwards `message` from `source` into `target`, refusing forwards that `options` or the company `settings` don't allow
+/// (see [`ForwardOptions`]).
+///
+/// Text, location and metainfo are decrypted with the key of `source` and encrypted again for `target`. Attached files are shared into `target`;
+/// if they are encrypted with a different key than the one of `target`, they are downloaded and uploaded again instead.
+/// Returns the forwarded message, decrypted.
+pub fn forward_message(state: &State, source: &impl Chat, target: &impl Chat, encryption_state: Option<&EncryptionState>, settings: &CompanySettings, message: &Message, options: ForwardOptions) -> Result<Message> {
+    let forward = prepare_forward(source, target, encryption_state, settings, &options, message)?;
+    let mut files = vec![];
+    for file in forward.message.files.iter().flatten() {
+        let shared = if forward.can_share(file) {
+            share_file(state, file, target)?
+        } else {
+            let data = download_file(state, forward.source_key.clone(), file.clone())?;
+            upload_file(state, target, forward.target_key.as_ref(), &file.name, data)?
+        };
+        files.push(shared.id);
+    }
+    let (prepared, options) = prepare_forwarded_text(target, encryption_state, &forward, files)?;
+    let mut forwarded = post_request::<chats::SendMessageResponse>(state,
+                                                                   "/message/send",
+                                                                   chats::SendMessageRequest::new(state, target.chat_type().to_string(), target.id().to_string(), prepared, options)?)?
+        .message;
+    forwarded.keep_originals();
+    if let Some(key) = &forward.target_key {
+        forwarded.decrypt(key.expose())?;
+    }
+    Ok(forwarded)
+}
@@ -1,12 +1,15 @@
 use serde::Serialize;
-use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde::de::DeserializeOwned;
 use crate::state::State;
 use crate::errors::{Errors, Result};
 use crate::request_types::APIResponse;
+use crate::requests::parse_payload;
 
 pub mod login;
 pub mod about;
 pub mod chats;
+pub mod keys;
+pub mod messages;
 pub fn post_request<T: DeserializeOwned>(state: &State, path: impl ToString, data: impl Serialize) -> Result<T> {
     let url = state.build_url(path);
     let response = reqwest::blocking::Client::new()
@@ -15,13 +18,18 @@ pub fn post_request<T: DeserializeOwned>(state: &State, path: impl ToString, dat
         .header("Accept", "application/json")
         .header("User-Agent", "Mozilla/5.0 (X11; Linux x86_64; rv:133.0) Gecko/20100101 Firefox/133.0")
         .send()?
-        .json::<APIResponse>().map_err(|e| Errors::NotJsonError(e))?;
-    if response.status.value != "OK" {
-        return Err(Errors::APIError(response.status.value, response.status.short_message, response.status.message))
-    }
-    T::deserialize(response.payload.clone().into_deserializer())
-        .map_err(|e| {
-            println!("{}", response.payload);
-            Errors::JsonDeserializeError(e)
-        })
+        .json::<APIResponse>().map_err(Errors::NotJsonError)?;
+    parse_payload(response)
+}
+/// Like [`post_request`], but sends `form` as `multipart/form-data`, e.g. to upload files.
+pub fn multipart_request<T: DeserializeOwned>(state: &State, path: impl ToString, form: reqwest::blocking::multipart::Form) -> Result<T> {
+    let url = state.build_url(path);
+    let response = reqwest::blocking::Client::new()
+        .post(url)
+        .multipart(form)
+        .header("Accept", "application/json")
+        .header("User-Agent", "Mozilla/5.0 (X11; Linux x86_64; rv:133.0) Gecko/20100101 Firefox/133.0")
+        .send()?
+        .json::<APIResponse>().map_err(Errors::NotJsonError)?;
+    parse_payload(response)
 }
\ No newline at end of file
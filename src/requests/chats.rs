@@ -1,18 +1,121 @@
 use crate::errors::Errors;
-use crate::request_types::about::RSAPublicKey;
 use crate::request_types::{chats, AuthOnlyRequest};
-use crate::requests::about::get_other_user_info;
-use crate::requests::post_request;
-use crate::state::State;
+pub use crate::request_types::chats::SendMessageOptions;
+pub(crate) use crate::request_types::chats::PreparedText;
+use crate::requests::about::get_companies;
+use crate::requests::{multipart_request, post_request};
+use crate::secret::SecretBytes;
+use crate::state::{EncryptionState, State};
 use crate::types::chats::channels::Channel;
 use crate::types::chats::conversations::Conversation;
-use crate::types::chats::messages::{File, Message, PossibleSender};
-use crate::types::user::others::User;
-use crate::Result;
-use base64::Engine;
-use openssl::rsa::Padding;
-use openssl::symm::{decrypt, Cipher};
-use std::fmt::{Display, Formatter};
+use crate::types::chats::messages::{self, File, Message, MessageLocation, MessageReplyTo};
+pub use crate::types::chats::ChatType;
+use crate::types::chats::{AnyChat, Chat};
+use crate::types::DetailedPersonInfo;
+use crate::verification::SignedContent;
+use crate::key_ring::ChatKeyRing;
+use crate::crypto::EncryptionFunction;
+use crate::{crypto, key_ring, Result};
+#[cfg(feature = "experimental")]
+use crate::crypto::PublicKey;
+#[cfg(feature = "experimental")]
+use crate::requests::about::get_other_user_info;
+#[cfg(feature = "experimental")]
+use crate::verification::{self, ContactKeys, SenderKeyCache, TrustPolicy, VerificationResult};
+
+const PAGE_SIZE: usize = 100;
+
+/// Encrypts `text` for `chat` (with `iv`, or a random one if `None`) and adds the other fields of `content`.
+/// Messages are only signed with the `experimental` feature, as the signed bytes (see [`SignedContent`]) aren't
+/// confirmed to match the official clients yet.
+/// Without an `encryption_state`, plain text is only sent into encrypted chats if
+/// [`SendMessageOptions::send_unencrypted_into_encrypted_chats`] is set.
+pub(crate) fn prepare_text(chat: &impl Chat, encryption_state: Option<&EncryptionState>, text: &str, content: SignedContent, iv: Option<Vec<u8>>, options: &SendMessageOptions) -> Result<PreparedText> {
+    let Some(encryption_state) = encryption_state else {
+        if chat.encrypted() && !options.send_unencrypted_into_encrypted_chats {
+            return Err(Errors::EncryptionRequired(chat.id().to_string()));
+        }
+        return Ok(PreparedText::plain(text, content, options.metainfo.clone()));
+    };
+    // metainfo is encrypted with the same key and IV as the text, like `Message::decrypt` expects it
+    let (text, iv, metainfo) = if chat.encrypted() {
+        let key = encryption_state.chat_key(chat)?;
+        let iv = match iv {
+            Some(iv) => iv,
+            None => messages::random_iv()?,
+        };
+        let metainfo = match &options.metainfo {
+            Some(metainfo) if !metainfo.is_empty() => Some(messages::encrypt_hex(key.expose(), &iv, metainfo)?),
+            metainfo => metainfo.clone(),
+        };
+        (messages::encrypt_hex(key.expose(), &iv, text)?, Some(hex::encode(iv)), metainfo)
+    } else {
+        (text.to_string(), None, options.metainfo.clone())
+    };
+    let content = SignedContent {
+        text,
+        iv,
+        ..content
+    };
+    Ok(PreparedText {
+        verification: if cfg!(feature = "experimental") {
+            encryption_state.sign_message(&content)?
+        } else {
+            String::new()
+        },
+        encrypted: chat.encrypted(),
+        text: content.text,
+        iv: content.iv,
+        files: content.files,
+        latitude: content.latitude,
+        longitude: content.longitude,
+        metainfo,
+    })
+}
+
+/// A location message as it is sent: coordinates encrypted with the same IV as the (empty) text in encrypted chats.
+/// The coordinates are sent together with the other fields of `content`.
+pub(crate) fn prepare_location(chat: &impl Chat, encryption_state: Option<&EncryptionState>, latitude: f64, longitude: f64, content: SignedContent, options: &SendMessageOptions) -> Result<PreparedText> {
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+        return Err(Errors::ValueError(format!("{latitude}, {longitude} isn't a valid location")));
+    }
+    let (latitude, longitude, iv) = match encryption_state {
+        Some(encryption_state) if chat.encrypted() => {
+            let key = encryption_state.chat_key(chat)?;
+            let iv = messages::random_iv()?;
+            (messages::encrypt_hex(key.expose(), &iv, &latitude.to_string())?,
+             messages::encrypt_hex(key.expose(), &iv, &longitude.to_string())?,
+             Some(iv))
+        },
+        _ => (latitude.to_string(), longitude.to_string(), None),
+    };
+    let content = SignedContent {
+        latitude: Some(latitude),
+        longitude: Some(longitude),
+        ..content
+    };
+    prepare_text(chat, encryption_state, "", content, iv, options)
+}
+/// File content and name as they are uploaded: encrypted with the chat key and a random IV in encrypted chats.
+pub(crate) struct PreparedUpload {
+    pub(crate) name: String,
+    pub(crate) data: Vec<u8>,
+    pub(crate) iv: Option<String>,
+}
+pub(crate) fn prepare_upload(chat: &impl Chat, key: Option<&SecretBytes>, name: &str, data: Vec<u8>) -> Result<PreparedUpload> {
+    if !chat.encrypted() {
+        return Ok(PreparedUpload { name: name.to_string(), data, iv: None });
+    }
+    let Some(key) = key else {
+        return Err(Errors::EncryptionRequired(chat.id().to_string()));
+    };
+    let iv = messages::random_iv()?;
+    Ok(PreparedUpload {
+        name: messages::encrypt_hex(key.expose(), &iv, name)?,
+        data: crypto::symmetric_encrypt(chat.cipher()?, key.expose(), Some(&iv), &data)?,
+        iv: Some(hex::encode(iv)),
+    })
+}
 
 pub async fn get_channels(state: &State, company_id: impl ToString) -> Result<Vec<Channel>> {
     Ok(post_request::<chats::ChannelsResponse>(state,
@@ -27,102 +130,284 @@ pub async fn get_conversations(state: &State, limit: usize, offset: usize, archi
         .await?.conversations)
 }
 
-pub async fn get_messages(state: &State, id: String, chat_type: ChatType, limit: usize, offset: usize, key: Option<Vec<u8>>) -> Result<Vec<Message>> {
+/// Fetches all members of a channel, page by page.
+pub async fn get_channel_members(state: &State, channel_id: impl ToString) -> Result<Vec<DetailedPersonInfo>> {
+    let channel_id = channel_id.to_string();
+    let mut members = vec![];
+    loop {
+        let page = post_request::<chats::ChannelMembersResponse>(state,
+                                                                 "/channels/members",
+                                                                 chats::ChannelMembersRequest::new(state, channel_id.clone(), PAGE_SIZE, members.len())?).await?
+            .members;
+        let done = page.len() < PAGE_SIZE;
+        members.extend(page);
+        if done {
+            return Ok(members);
+        }
+    }
+}
+/// Fetches all (not archived) conversations, page by page.
+pub async fn get_all_conversations(state: &State) -> Result<Vec<Conversation>> {
+    let mut conversations = vec![];
+    loop {
+        let page = get_conversations(state, PAGE_SIZE, conversations.len(), 0, vec![]).await?;
+        let done = page.len() < PAGE_SIZE;
+        conversations.extend(page);
+        if done {
+            return Ok(conversations);
+        }
+    }
+}
+/// Fetches the channels of every company the user is a member of, as well as all conversations.
+pub async fn get_all_chats(state: &State) -> Result<Vec<AnyChat>> {
+    let mut chats = vec![];
+    for company in get_companies(state).await? {
+        chats.extend(get_channels(state, company.id).await?.into_iter().map(AnyChat::from));
+    }
+    chats.extend(get_all_conversations(state).await?.into_iter().map(AnyChat::from));
+    Ok(chats)
+}
+/// `key` is the chat's AES key as returned by [`EncryptionState::chat_key`].
+pub async fn get_messages(state: &State, id: String, chat_type: ChatType, limit: usize, offset: usize, key: Option<SecretBytes>) -> Result<Vec<Message>> {
     let mut messages = post_request::<chats::MessageResponse>(state,
                                                           "/message/content",
                                                           chats::MessageRequest::new(state, id, chat_type.to_string(), limit, offset)?)
         .await?.messages;
-    let _ = messages.iter_mut().map(|m| m.original_text = m.text.clone()).collect::<Vec<()>>();
+    let _ = messages.iter_mut().map(|m| m.keep_originals()).collect::<Vec<()>>();
     let Some(key) = key else {
         return Ok(messages);
     };
     for message in &mut messages {
-        if message.encrypted != Some(true) || message.text.is_none() || message.text == Some("".to_string()) {
-            continue;
-        }
-        let encrypted = hex::decode(message.text.clone().unwrap()).map_err(|e| Errors::HexError(e))?;
-        let iv_ = match &message.iv {
-            Some(iv) => Some(hex::decode(iv).map_err(|e| Errors::HexError(e))?),
-            None => None,
-        };
-        let iv = match &iv_ {
-            Some(iv) => Some(&**iv),
-            None => None
+        message.decrypt(key.expose())?;
+    }
+    Ok(messages)
+}
+/// Like [`get_messages`], but takes the chat itself and decrypts the messages with the chat's key.
+/// Pass a `key_ring` to also decrypt messages sent before the key was rotated.
+pub async fn get_chat_messages(state: &State, chat: &impl Chat, encryption_state: &EncryptionState, key_ring: Option<&ChatKeyRing>, limit: usize, offset: usize) -> Result<Vec<Message>> {
+    let Some(key_ring) = key_ring.filter(|key_ring| chat.encrypted() && key_ring.contains_chat(chat)) else {
+        let key = if chat.encrypted() {
+            Some(encryption_state.chat_key(chat)?)
+        } else {
+            None
         };
-        let decrypted = decrypt(Cipher::aes_256_cbc(), &*key, iv, &*encrypted)
-            .map_err(|e| Errors::EncryptionError(e))?;
-        let text = String::from_utf8(decrypted).map_err(|e| Errors::StringDecodeError(e))?;
-        message.text = Some(text);
+        return get_messages(state, chat.id().to_string(), chat.chat_type(), limit, offset, key).await;
+    };
+    let keys = key_ring::known_keys(key_ring, encryption_state, chat)?;
+    let mut messages = get_messages(state, chat.id().to_string(), chat.chat_type(), limit, offset, None).await?;
+    for message in &mut messages {
+        key_ring::decrypt_with_keys(message, &keys)?;
     }
     Ok(messages)
 }
-pub async fn download_file(state: &State, key: Option<Vec<u8>>, file: File) -> Result<Vec<u8>> {
+pub async fn download_file(state: &State, key: Option<SecretBytes>, file: File) -> Result<Vec<u8>> {
     let raw_data = reqwest::Client::new()
         .post(state.build_url("/file/download"))
         .query(&chats::FileDownloadQuery { id: file.id })
         .form(&AuthOnlyRequest::new(state)?)
-        .send().await
-        .map_err(|e| Errors::RequestError(e))?
-        .bytes().await
-        .map_err(|e| Errors::RequestError(e))?
+        .send().await?
+        .bytes().await?
         .to_vec();
     if !file.encrypted || key.is_none() {
         return Ok(raw_data);
     }
     let iv_ = match file.e2e_iv {
-        Some(iv) => Some(hex::decode(iv).map_err(|e| Errors::HexError(e))?),
+        Some(iv) => Some(hex::decode(iv)?),
         None => None
     };
-    let iv = match &iv_ {
-        Some(iv) => Some(&**iv),
-        None => None,
-    };
-    decrypt(Cipher::aes_256_cbc(), key.unwrap().as_ref(), iv, &*raw_data)
-        .map_err(|e| Errors::EncryptionError(e))
+    crypto::symmetric_decrypt(EncryptionFunction::Aes256Cbc, key.unwrap().expose(), iv_.as_deref(), &raw_data)
 }
+/// Verifies the hash and signature of a message against its sender's public signing key.
+/// The sender's keys have to pass `policy` (see [`ContactKeys::verify`]) before the signing key is used.
+/// Sender keys are taken from (and stored in) `cache`, so only unknown senders are fetched.
 #[cfg(feature = "experimental")]
-pub async fn verify_signature(state: &State, message: &Message) -> Result<bool> {
+pub async fn verify_signature(state: &State, cache: &SenderKeyCache, message: &Message, ca_key: &PublicKey, policy: TrustPolicy) -> Result<VerificationResult> {
     if message.verification.is_none() {
-        return Ok(true);
+        return Ok(VerificationResult::Unsigned);
     }
-    let sender_id = match &message.sender {
-        PossibleSender::MessageSender(s) => s.id.clone().unwrap(),
-        PossibleSender::String(_s) => return Err(Errors::OtherErrors("Can't verify hash of unknown sender.".to_string()))
+    let Some(sender_id) = verification::sender_id(message) else {
+        return Ok(VerificationResult::SenderKeyUnavailable);
     };
-    let target = hex::decode(message.verification.clone().unwrap())?;
-    let user_info: User = get_other_user_info(state, sender_id).await?;
-    let rsa_key = RSAPublicKey::from_str(&*user_info.public_signing_key)?.to_key()?;
-    let key = openssl::pkey::PKey::from_rsa(rsa_key.clone())?;
-    let data = message.text.clone().unwrap().into_bytes();
-    if message.encrypted == Some(true) {
-        hex::decode(message.original_text.clone().unwrap())?
-    } else {
-        message.original_text.clone().unwrap().into_bytes()
+    let key = match cache.get(&sender_id) {
+        Some(key) => key,
+        None => {
+            let user_info = get_other_user_info(state, sender_id.clone()).await?;
+            let key = match ContactKeys::from(&user_info).trusted_signing_key(ca_key, policy) {
+                Ok(Some(key)) => key,
+                Ok(None) => return Ok(VerificationResult::SenderKeyUnavailable),
+                Err(Errors::UntrustedKey(_, trust)) => return Ok(VerificationResult::UntrustedSenderKey(trust)),
+                Err(e) => return Err(e),
+            };
+            cache.insert(sender_id, key.clone());
+            key
+        }
     };
-    let target_hash = base64::engine::general_purpose::STANDARD.decode(message.hash.clone().unwrap())?;
-    let generated_hash = openssl::hash::hash(openssl::hash::MessageDigest::sha256(), &*data)?.to_vec();
-    println!("{}", generated_hash == target_hash);
-    let mut buff = vec![0; rsa_key.size() as usize];
-    let buff_len = rsa_key.public_decrypt(&*generated_hash, &mut buff, Padding::NONE)?;
-    buff.truncate(buff_len);
-    println!("{}", buff == target);
-    println!("{:?}", buff);
-    println!("{buff_len}");
+    verification::verify_with_key(message, &key)
+}
+/// Sends an unencrypted text message into `chat` and returns it as stored by the server.
+/// Fails with [`Errors::EncryptionRequired`] for encrypted chats unless
+/// [`SendMessageOptions::send_unencrypted_into_encrypted_chats`] is set,
+/// use [`send_chat_message`] to send encrypted messages.
+pub async fn send_message(state: &State, chat: &impl Chat, text: impl ToString, options: SendMessageOptions) -> Result<Message> {
+    let prepared = prepare_text(chat, None, &text.to_string(), SignedContent::default(), None, &options)?;
+    let mut message = post_request::<chats::SendMessageResponse>(state,
+                                                                 "/message/send",
+                                                                 chats::SendMessageRequest::new(state, chat.chat_type().to_string(), chat.id().to_string(), prepared, options)?)
+        .await?.message;
+    message.keep_originals();
+    Ok(message)
+}
+/// Sends a text message into `chat`, encrypted with the chat key if the chat is encrypted
+/// and, with the `experimental` feature, signed with our signing key if an `encryption_state` is given.
+/// The returned message is decrypted again.
+pub async fn send_chat_message(state: &State, chat: &impl Chat, encryption_state: Option<&EncryptionState>, text: impl ToString, options: SendMessageOptions) -> Result<Message> {
+    let text = text.to_string();
+    let prepared = prepare_text(chat, encryption_state, &text, SignedContent::default(), None, &options)?;
+    let encrypted = prepared.encrypted;
+    let metainfo = options.metainfo.clone();
+    let mut message = post_request::<chats::SendMessageResponse>(state,
+                                                                 "/message/send",
+                                                                 chats::SendMessageRequest::new(state, chat.chat_type().to_string(), chat.id().to_string(), prepared, options)?)
+        .await?.message;
+    message.keep_originals();
+    if encrypted {
+        message.text = Some(text);
+        message.metainfo = metainfo;
+    }
+    Ok(message)
+}
+/// Sends a location (in degrees) into `chat` like [`send_chat_message`], encrypting the coordinates in encrypted chats.
+pub async fn send_location(state: &State, chat: &impl Chat, encryption_state: Option<&EncryptionState>, latitude: f64, longitude: f64, options: SendMessageOptions) -> Result<Message> {
+    let prepared = prepare_location(chat, encryption_state, latitude, longitude, SignedContent::default(), &options)?;
+    let encrypted = prepared.encrypted;
+    let metainfo = options.metainfo.clone();
+    let mut message = post_request::<chats::SendMessageResponse>(state,
+                                                                 "/message/send",
+                                                                 chats::SendMessageRequest::new(state, chat.chat_type().to_string(), chat.id().to_string(), prepared, options)?)
+        .await?.message;
+    message.keep_originals();
+    if encrypted {
+        message.text = Some(String::new());
+        message.location = Some(MessageLocation::new(latitude, longitude));
+        message.metainfo = metainfo;
+    }
+    Ok(message)
+}
+/// Answers `message` in `chat` like [`send_chat_message`], referencing the id, hash and signature of `message`.
+pub async fn reply_to(state: &State, chat: &impl Chat, encryption_state: Option<&EncryptionState>, message: &Message, text: impl ToString, options: SendMessageOptions) -> Result<Message> {
+    send_chat_message(state, chat, encryption_state, text, SendMessageOptions { reply_to: Some(MessageReplyTo::from(message)), ..options }).await
+}
+/// Fetches all messages of the thread `message` belongs to, oldest first.
+/// `key` is the chat's AES key as returned by [`EncryptionState::chat_key`].
+pub async fn get_thread(state: &State, message: &Message, key: Option<SecretBytes>) -> Result<Vec<Message>> {
+    let thread_id = message.thread_root().to_string();
+    let mut messages = vec![];
+    loop {
+        let page = post_request::<chats::MessageResponse>(state,
+                                                          "/message/thread",
+                                                          chats::ThreadRequest::new(state, thread_id.clone(), PAGE_SIZE, messages.len())?).await?
+            .messages;
+        let done = page.len() < PAGE_SIZE;
+        messages.extend(page);
+        if done {
+            break;
+        }
+    }
+    sort_thread(&mut messages);
+    for message in &mut messages {
+        message.keep_originals();
+        if let Some(key) = &key {
+            message.decrypt(key.expose())?;
+        }
+    }
+    Ok(messages)
+}
+/// Orders messages by the time they were sent, using the id for messages sent in the same second.
+pub(crate) fn sort_thread(messages: &mut [Message]) {
+    messages.sort_by_key(|message| (message.time.as_ref().and_then(|time| time.parse::<u64>().ok()).unwrap_or_default(), message.id));
+}
+/// Uploads `data` as a file into `chat`. In encrypted chats, content and name are encrypted with the chat `key`.
+/// Returns the uploaded file with its name decrypted again.
+pub async fn upload_file(state: &State, chat: &impl Chat, key: Option<&SecretBytes>, name: impl ToString, data: Vec<u8>) -> Result<File> {
+    let upload = prepare_upload(chat, key, &name.to_string(), data)?;
+    let request = chats::FileUploadRequest::new(state, chat.chat_type().to_string(), chat.id().to_string(), upload.iv.is_some(), upload.iv)?;
+    let form = request.fields().into_iter()
+        .fold(reqwest::multipart::Form::new(), |form, (field, value)| form.text(field, value))
+        .part("file", reqwest::multipart::Part::bytes(upload.data).file_name(upload.name));
+    let mut file = multipart_request::<chats::FileResponse>(state, "/file/upload", form).await?.file;
+    if let Some(key) = key {
+        file.decrypt(key.expose())?;
+    }
+    Ok(file)
+}
+/// Shares an existing file into `target` without uploading it again.
+/// The content isn't re-encrypted, so this only works if `target` can read it (same or no key).
+pub async fn share_file(state: &State, file: &File, target: &impl Chat) -> Result<File> {
+    Ok(post_request::<chats::FileResponse>(state,
+                                           "/file/share",
+                                           chats::ShareFileRequest::new(state, file.id.clone(), target.chat_type().to_string(), target.id().to_string())?).await?
+        .file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use serde_json::json;
+    use crate::test_support::{channel, encryption_state, message};
 
-    let mut verifier = openssl::sign::Verifier::new(openssl::hash::MessageDigest::sha256(), &key)?;
-    verifier.update(&*data)?;
-    let res = verifier.verify(&*target).map_err(Errors::from);
-    res
-}
-pub enum ChatType {
-    Channel,
-    Conversation,
-}
-impl Display for ChatType {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", match self {
-            ChatType::Channel => "channel",
-            ChatType::Conversation => "conversation",
-        })
-    }
-}
\ No newline at end of file
+    const KEY: [u8; 32] = [7; 32];
+
+    #[test]
+    fn metainfo_is_encrypted_with_the_text() {
+        let state = encryption_state();
+        let wrapped_key = BASE64.encode(state.encrypt_for(state.public_key(), KEY).unwrap());
+        let chat = channel(json!({"encrypted": true, "key": wrapped_key}));
+        let options = SendMessageOptions { metainfo: Some("{\"mime\": \"text/plain\"}".to_string()), ..SendMessageOptions::default() };
+        let prepared = prepare_text(&chat, Some(&state), "hello", SignedContent::default(), None, &options).unwrap();
+        let iv = prepared.iv.as_deref();
+        assert_eq!(messages::decrypt_hex(&KEY, iv, &prepared.text).unwrap(), "hello");
+        assert_eq!(messages::decrypt_hex(&KEY, iv, prepared.metainfo.as_deref().unwrap()).unwrap(), "{\"mime\": \"text/plain\"}");
+
+        let plain = prepare_text(&channel(json!({})), Some(&state), "hello", SignedContent::default(), None, &options).unwrap();
+        assert_eq!(plain.metainfo, options.metainfo);
+    }
+    #[test]
+    fn messages_are_only_signed_with_experimental() {
+        let state = encryption_state();
+        let prepared = prepare_text(&channel(json!({})), Some(&state), "hello", SignedContent::default(), None, &SendMessageOptions::default()).unwrap();
+        assert_eq!(prepared.verification.is_empty(), !cfg!(feature = "experimental"));
+    }
+    #[test]
+    fn plain_text_into_encrypted_chats_is_refused() {
+        let chat = channel(json!({"encrypted": true}));
+        let refused = prepare_text(&chat, None, "hello", SignedContent::default(), None, &SendMessageOptions::default());
+        assert!(matches!(refused, Err(Errors::EncryptionRequired(id)) if id == chat.id()));
+
+        let options = SendMessageOptions { send_unencrypted_into_encrypted_chats: true, ..SendMessageOptions::default() };
+        let prepared = prepare_text(&chat, None, "hello", SignedContent::default(), None, &options).unwrap();
+        assert_eq!((prepared.text.as_str(), prepared.encrypted), ("hello", false));
+        assert!(prepare_text(&channel(json!({})), None, "hello", SignedContent::default(), None, &SendMessageOptions::default()).is_ok());
+    }
+    #[test]
+    fn threads_are_sorted_by_time_then_id() {
+        let mut thread = vec![
+            message("42", json!({"id": 4, "time": "200"})),
+            message("42", json!({"id": 3, "time": "100"})),
+            message("42", json!({"id": 2, "time": "200"})),
+            // missing or unreadable times sort first instead of failing
+            message("42", json!({"id": 5, "time": "yesterday"})),
+            message("42", json!({"id": 6})),
+        ];
+        sort_thread(&mut thread);
+        assert_eq!(thread.iter().map(|message| message.id).collect::<Vec<_>>(), [5, 6, 3, 2, 4]);
+    }
+    #[test]
+    fn thread_root_is_taken_from_the_message() {
+        // no parent chain is followed, so missing parents and cycles can't make the lookup fail
+        assert_eq!(message("42", json!({"id": 7})).thread_root(), 7);
+        assert_eq!(message("42", json!({"id": 7, "thread_id": 3})).thread_root(), 3);
+        assert_eq!(message("42", json!({"id": 7, "thread_id": 7})).thread_root(), 7);
+    }
+}
@@ -0,0 +1,232 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use crate::crypto::PublicKey;
+use serde_json::Value;
+use web_time::{SystemTime, UNIX_EPOCH};
+use crate::errors::Errors;
+use crate::key_ring::ChatKeyRing;
+use crate::request_types::keys;
+use crate::requests::about::{get_other_user_info, get_user_info};
+use crate::requests::chats::{get_all_chats, get_channel_members};
+use crate::requests::post_request;
+use crate::secret::SecretBytes;
+use crate::state::{EncryptionState, State};
+use crate::types::chats::{AnyChat, Chat};
+use crate::types::user::others::User;
+use crate::types::DetailedPersonInfo;
+use crate::verification::{ContactKeys, KeyTrust, TrustPolicy};
+use crate::Result;
+
+/// Outcome of sending the chat key to a single member.
+#[derive(Debug)]
+pub enum KeyDistributionStatus {
+    /// The key has been encrypted for the member and uploaded.
+    Sent,
+    /// The member's public key didn't satisfy the [`TrustPolicy`].
+    Untrusted(KeyTrust),
+    /// Fetching the member's keys, encrypting or uploading failed.
+    Failed(Errors),
+}
+impl From<Errors> for KeyDistributionStatus {
+    fn from(error: Errors) -> Self {
+        match error {
+            Errors::UntrustedKey(_, trust) => KeyDistributionStatus::Untrusted(trust),
+            other => KeyDistributionStatus::Failed(other),
+        }
+    }
+}
+#[derive(Debug)]
+pub struct MemberKeyReport {
+    pub user_id: String,
+    pub status: KeyDistributionStatus,
+}
+/// A member asking for the key of a chat.
+#[derive(Clone, Debug)]
+pub struct KeyRequest {
+    pub chat: AnyChat,
+    pub requester: User,
+    pub time: Option<String>,
+    /// Result of [`ContactKeys::verify`] for the requester's public key.
+    pub trust: KeyTrust,
+}
+
+/// A chat key encrypted for one member (base64) and signed by us (hex).
+pub(crate) struct WrappedKey {
+    pub(crate) key: String,
+    pub(crate) signature: String,
+}
+pub(crate) fn wrap_chat_key(encryption_state: &EncryptionState, contact: &ContactKeys, chat_key: &SecretBytes, ca_key: &PublicKey, policy: TrustPolicy) -> Result<WrappedKey> {
+    sign_wrapped_key(encryption_state, encryption_state.encrypt_for_contact(contact, ca_key, policy, chat_key.expose())?)
+}
+/// [`wrap_chat_key`] for ourselves, with the key we hold instead of the one the server publishes.
+pub(crate) fn wrap_own_chat_key(encryption_state: &EncryptionState, chat_key: &SecretBytes) -> Result<WrappedKey> {
+    sign_wrapped_key(encryption_state, encryption_state.encrypt_for(encryption_state.public_key(), chat_key.expose())?)
+}
+fn sign_wrapped_key(encryption_state: &EncryptionState, encrypted: Vec<u8>) -> Result<WrappedKey> {
+    Ok(WrappedKey {
+        signature: hex::encode(encryption_state.sign(&*encrypted)?),
+        key: BASE64.encode(encrypted),
+    })
+}
+
+pub async fn get_members_without_keys(state: &State, chat: &impl Chat) -> Result<Vec<DetailedPersonInfo>> {
+    Ok(post_request::<keys::MembersWithoutKeysResponse>(state,
+                                                        "/security/members_without_keys",
+                                                        keys::KeyTargetRequest::new(state, chat.chat_type().to_string(), chat.id().to_string())?)
+        .await?.users)
+}
+/// Encrypts the chat key for a single member and uploads it.
+pub async fn send_chat_key(state: &State, encryption_state: &EncryptionState, chat: &impl Chat, chat_key: &SecretBytes, user_id: String, ca_key: &PublicKey, policy: TrustPolicy) -> Result<()> {
+    let user = get_other_user_info(state, user_id.clone()).await?;
+    let wrapped = wrap_chat_key(encryption_state, &ContactKeys::from(&user), chat_key, ca_key, policy)?;
+    post_request::<Value>(state,
+                          "/security/set_missing_key",
+                          keys::SetMissingKeyRequest::new(state, chat.chat_type().to_string(), chat.id().to_string(), user_id, wrapped.key, wrapped.signature)?)
+        .await?;
+    Ok(())
+}
+/// Sends the chat key to every member that doesn't have it yet.
+/// Keys of members that don't satisfy `policy` are skipped, see [`ContactKeys::verify`].
+pub async fn distribute_chat_key(state: &State, encryption_state: &EncryptionState, chat: &impl Chat, ca_key: &PublicKey, policy: TrustPolicy) -> Result<Vec<MemberKeyReport>> {
+    if chat.num_members_without_keys() == 0 {
+        return Ok(vec![]);
+    }
+    let chat_key = encryption_state.chat_key(chat)?;
+    let mut reports = vec![];
+    for member in get_members_without_keys(state, chat).await? {
+        let Some(user_id) = member.id else {
+            continue;
+        };
+        let status = match send_chat_key(state, encryption_state, chat, &chat_key, user_id.clone(), ca_key, policy).await {
+            Ok(()) => KeyDistributionStatus::Sent,
+            Err(e) => e.into(),
+        };
+        reports.push(MemberKeyReport { user_id, status });
+    }
+    Ok(reports)
+}
+/// Lists the key requests for a single chat.
+pub async fn get_key_requests(state: &State, chat: &AnyChat, ca_key: &PublicKey) -> Result<Vec<KeyRequest>> {
+    let requests = post_request::<keys::KeyRequestsResponse>(state,
+                                                             "/security/get_key_requests",
+                                                             keys::KeyTargetRequest::new(state, chat.chat_type().to_string(), chat.id().to_string())?)
+        .await?.requests;
+    let mut result = vec![];
+    for request in requests {
+        let Some(user_id) = request.user.id else {
+            continue;
+        };
+        let requester = get_other_user_info(state, user_id).await?;
+        result.push(KeyRequest {
+            chat: chat.clone(),
+            trust: ContactKeys::from(&requester).verify(ca_key)?,
+            requester,
+            time: request.time,
+        });
+    }
+    Ok(result)
+}
+/// Lists the pending key requests of all chats we hold the key of.
+pub async fn get_pending_key_requests(state: &State, ca_key: &PublicKey) -> Result<Vec<KeyRequest>> {
+    let mut requests = vec![];
+    for chat in get_all_chats(state).await? {
+        if chat.key_requested() && chat.key().is_some() {
+            requests.extend(get_key_requests(state, &chat, ca_key).await?);
+        }
+    }
+    Ok(requests)
+}
+/// Answers a key request by encrypting the chat key for the requester and sending it.
+pub async fn approve_key_request(state: &State, encryption_state: &EncryptionState, request: &KeyRequest, ca_key: &PublicKey, policy: TrustPolicy) -> Result<()> {
+    let chat_key = encryption_state.chat_key(&request.chat)?;
+    send_chat_key(state, encryption_state, &request.chat, &chat_key, request.requester.id.clone(), ca_key, policy).await
+}
+pub async fn reject_key_request(state: &State, request: &KeyRequest) -> Result<()> {
+    post_request::<Value>(state,
+                          "/security/reject_key_request",
+                          keys::RejectKeyRequest::new(state, request.chat.chat_type().to_string(), request.chat.id().to_string(), request.requester.id.clone())?)
+        .await?;
+    Ok(())
+}
+/// Replaces the chat key with a new random key for the chat's cipher, encrypted for every current member, e.g. after someone left.
+///
+/// The previous and the new key are added to `key_ring`, so older messages stay readable (see `get_chat_messages`).
+/// Members whose keys don't satisfy `policy` don't receive the new key and are reported as such.
+/// Our own copy is encrypted with our own key without checking it against the CA.
+/// Nothing is rotated unless the new key can be encrypted for us and for at least one other member.
+pub async fn rotate_chat_key(state: &State, encryption_state: &EncryptionState, chat: &impl Chat, key_ring: &mut ChatKeyRing, ca_key: &PublicKey, policy: TrustPolicy) -> Result<Vec<MemberKeyReport>> {
+    if !key_ring.contains_chat(chat) {
+        if let Ok(previous) = encryption_state.chat_key(chat) {
+            key_ring.add(encryption_state, chat, &previous, 0)?;
+        }
+    }
+    let mut new_key = SecretBytes::zeroed(chat.cipher()?.key_len());
+    crate::crypto::random_bytes(new_key.expose_mut())?;
+    let own_id = get_user_info(state).await?.id;
+
+    let members = match chat.known_members() {
+        Some(members) => members.to_vec(),
+        None => get_channel_members(state, chat.id()).await?,
+    };
+    let mut keys = vec![];
+    let mut reports = vec![];
+    for user_id in members.into_iter().filter_map(|member| member.id) {
+        let wrapped = if user_id == own_id {
+            wrap_own_chat_key(encryption_state, &new_key)
+        } else {
+            match get_other_user_info(state, user_id.clone()).await {
+                Ok(user) => wrap_chat_key(encryption_state, &ContactKeys::from(&user), &new_key, ca_key, policy),
+                Err(e) => Err(e),
+            }
+        };
+        match wrapped {
+            Ok(wrapped) => keys.push(keys::MemberKey { user_id, key: wrapped.key, key_signature: wrapped.signature }),
+            Err(e) => reports.push(MemberKeyReport { user_id, status: e.into() }),
+        }
+    }
+    check_rotation_keys(&own_id, &keys)?;
+    post_request::<Value>(state,
+                          "/security/rotate_key",
+                          keys::RotateKeyRequest::new(state, chat.chat_type().to_string(), chat.id().to_string(), &keys)?).await?;
+    key_ring.add(encryption_state, chat, &new_key, unix_time())?;
+    reports.extend(keys.into_iter().map(|key| MemberKeyReport { user_id: key.user_id, status: KeyDistributionStatus::Sent }));
+    Ok(reports)
+}
+/// Refuses a rotation that would lock us out of the chat or leave nobody else able to read it.
+pub(crate) fn check_rotation_keys(own_id: &str, keys: &[keys::MemberKey]) -> Result<()> {
+    if !keys.iter().any(|key| key.user_id == own_id) {
+        return Err(Errors::ValueError("the new chat key couldn't be encrypted for our own key".to_string()));
+    }
+    if !keys.iter().any(|key| key.user_id != own_id) {
+        return Err(Errors::ValueError("the new chat key couldn't be encrypted for any other member".to_string()));
+    }
+    Ok(())
+}
+pub(crate) fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::encryption_state;
+
+    fn member_key(user_id: &str) -> keys::MemberKey {
+        keys::MemberKey { user_id: user_id.to_string(), key: String::new(), key_signature: String::new() }
+    }
+
+    #[test]
+    fn rotation_needs_us_and_another_member() {
+        assert!(check_rotation_keys("1", &[]).is_err());
+        assert!(check_rotation_keys("1", &[member_key("2")]).is_err());
+        assert!(check_rotation_keys("1", &[member_key("1")]).is_err());
+        assert!(check_rotation_keys("1", &[member_key("2"), member_key("1")]).is_ok());
+    }
+    #[test]
+    fn own_chat_key_is_readable_by_us() {
+        let state = encryption_state();
+        let chat_key = SecretBytes::new(vec![7; 32]);
+        let wrapped = wrap_own_chat_key(&state, &chat_key).unwrap();
+        assert_eq!(state.decrypt(wrapped.key).unwrap().expose(), chat_key.expose());
+    }
+}
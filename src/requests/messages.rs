@@ -0,0 +1,518 @@
+use serde_json::Value;
+use crate::errors::Errors;
+use crate::key_ring::{self, ChatKeyRing};
+use crate::request_types::{chats, messages};
+use crate::request_types::chats::{PreparedText, SendMessageOptions};
+use crate::requests::chats::{download_file, get_all_chats, get_channel_members, prepare_location, prepare_text, share_file, upload_file};
+use crate::requests::about::get_user_info;
+use crate::requests::keys::unix_time;
+use crate::requests::post_request;
+use crate::secret::SecretBytes;
+use crate::state::{EncryptionState, State};
+use crate::types::chats::channels::Channel;
+use crate::types::chats::messages::{File, Message, MessageSeen};
+use crate::types::chats::{AnyChat, Chat, ChatType};
+use crate::types::user::companies::CompanySettings;
+use crate::types::DetailedPersonInfo;
+use crate::verification::{self, SignedContent};
+use crate::Result;
+
+/// Whether the user `own_id` may delete `message` in `chat`: its own messages if the company allows deleting messages,
+/// and any message in channels it manages.
+pub fn may_delete_messages(chat: &AnyChat, settings: &CompanySettings, message: &Message, own_id: &str) -> bool {
+    let own = verification::sender_id(message).as_deref() == Some(own_id);
+    (own && settings.can_delete_messages) || matches!(chat, AnyChat::Channel(channel) if channel.membership.may_manage)
+}
+/// Encrypts (and signs) the new text of `message` like [`send_chat_message`](crate::requests::chats::send_chat_message).
+/// Only messages sent by `own_id` can be edited.
+pub(crate) fn prepare_edit(chat: &impl Chat, encryption_state: Option<&EncryptionState>, message: &Message, text: &str, own_id: &str) -> Result<PreparedText> {
+    if verification::sender_id(message).as_deref() != Some(own_id) {
+        return Err(Errors::PermissionDenied(format!("editing message {} of another user", message.id)));
+    }
+    // location, metainfo and payload are encrypted with the message's IV and aren't sent again, so a new text would
+    // have to reuse that IV under the same key, which CBC doesn't allow
+    let shares_iv = message.location.is_some() || message.metainfo.is_some() || message.messagePayload.as_ref().is_some_and(|payload| !payload.is_null());
+    if chat.encrypted() && shares_iv {
+        return Err(Errors::ValueError(format!("message {} shares its IV with its location, metainfo or payload and can't be edited", message.id)));
+    }
+    prepare_text(chat, encryption_state, text, SignedContent::from(message), None, &SendMessageOptions::default())
+}
+/// The edited message as echoed by the server, or `message` with the new text if it isn't echoed.
+pub(crate) fn edited_message(chat: &impl Chat, encryption_state: Option<&EncryptionState>, message: &Message, echoed: Option<Message>, prepared: PreparedText) -> Result<Message> {
+    let mut edited = echoed.unwrap_or_else(|| {
+        let mut edited = message.clone();
+        edited.restore_originals();
+        Message {
+            text: Some(prepared.text),
+            iv: prepared.iv.or(edited.iv.take()),
+            verification: Some(prepared.verification).filter(|verification| !verification.is_empty()),
+            encrypted: Some(prepared.encrypted),
+            ..edited
+        }
+    });
+    edited.keep_originals();
+    if let (true, Some(encryption_state)) = (prepared.encrypted, encryption_state) {
+        edited.decrypt(encryption_state.chat_key(chat)?.expose())?;
+    }
+    Ok(edited)
+}
+/// `message` marked as deleted now, for endpoints that don't echo the deleted message.
+pub(crate) fn deleted_message(message: &Message, echoed: Option<Message>) -> Message {
+    echoed.unwrap_or_else(|| Message {
+        deleted: Some(unix_time().to_string()),
+        ..message.clone()
+    })
+}
+
+/// Options of [`forward_message`].
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct ForwardOptions {
+    /// Whether messages may be forwarded out of encrypted chats into unencrypted ones, which publishes them in plain text.
+    pub allow_unencrypted_target: bool,
+}
+/// Refuses forwards out of encrypted chats into unencrypted ones unless `options` allow them, and unencrypted files
+/// into encrypted chats unless the company `settings` set `share_unencrypted_files_into_encrypted_chats`.
+fn check_forward(source: &impl Chat, target: &impl Chat, message: &Message, settings: &CompanySettings, options: &ForwardOptions) -> Result<()> {
+    if source.encrypted() && !target.encrypted() && !options.allow_unencrypted_target {
+        return Err(Errors::PermissionDenied(format!("forwarding messages out of encrypted chat {} into unencrypted chat {}", source.id(), target.id())));
+    }
+    let has_unencrypted_files = message.files.iter().flatten().any(|file| !file.encrypted);
+    if target.encrypted() && has_unencrypted_files && !settings.share_unencrypted_files_into_encrypted_chats {
+        return Err(Errors::PermissionDenied(format!("sharing unencrypted files into encrypted chat {}", target.id())));
+    }
+    Ok(())
+}
+/// A message about to be forwarded: decrypted with the source key, together with the keys of both chats.
+pub(crate) struct PreparedForward {
+    pub(crate) message: Message,
+    pub(crate) source_key: Option<SecretBytes>,
+    pub(crate) target_key: Option<SecretBytes>,
+}
+impl PreparedForward {
+    /// Files can be shared as they are if they aren't encrypted or both chats use the same key,
+    /// otherwise they have to be downloaded and uploaded into the target again.
+    pub(crate) fn can_share(&self, file: &File) -> bool {
+        !file.encrypted || (self.source_key.is_some() && self.source_key == self.target_key)
+    }
+}
+/// The key of `chat` if it is encrypted, which requires `encryption_state`.
+fn forward_key(chat: &impl Chat, encryption_state: Option<&EncryptionState>) -> Result<Option<SecretBytes>> {
+    match encryption_state {
+        _ if !chat.encrypted() => Ok(None),
+        Some(encryption_state) => encryption_state.chat_key(chat).map(Some),
+        None => Err(Errors::EncryptionRequired(chat.id().to_string())),
+    }
+}
+/// Checks the forward against `settings` and `options` and decrypts `message` with the key of `source`.
+pub(crate) fn prepare_forward(source: &impl Chat, target: &impl Chat, encryption_state: Option<&EncryptionState>, settings: &CompanySettings, options: &ForwardOptions, message: &Message) -> Result<PreparedForward> {
+    check_forward(source, target, message, settings, options)?;
+    let source_key = forward_key(source, encryption_state)?;
+    let target_key = forward_key(target, encryption_state)?;
+    // start from the message as it was received, it may or may not have been decrypted already
+    let mut plain = message.clone();
+    plain.restore_originals();
+    if let Some(key) = &source_key {
+        plain.decrypt(key.expose())?;
+    }
+    Ok(PreparedForward { message: plain, source_key, target_key })
+}
+/// Text, location and metainfo of the forwarded message, encrypted for `target`, together with the already shared `files`.
+pub(crate) fn prepare_forwarded_text(target: &impl Chat, encryption_state: Option<&EncryptionState>, forward: &PreparedForward, files: Vec<String>) -> Result<(PreparedText, SendMessageOptions)> {
+    let message = &forward.message;
+    let options = SendMessageOptions {
+        r#type: message.r#type.clone().unwrap_or_else(|| "text".to_string()),
+        is_forwarded: true,
+        metainfo: message.metainfo.clone(),
+        ..SendMessageOptions::default()
+    };
+    let content = SignedContent { files, ..SignedContent::default() };
+    let prepared = match message.coordinates() {
+        Some((latitude, longitude)) => prepare_location(target, encryption_state, latitude, longitude, content, &options)?,
+        None => prepare_text(target, encryption_state, message.text.as_deref().unwrap_or_default(), content, None, &options)?,
+    };
+    Ok((prepared, options))
+}
+
+const PAGE_SIZE: usize = 100;
+
+/// `message` after liking (or unliking) it, for endpoints that don't echo the message.
+pub(crate) fn liked_message(message: &Message, echoed: Option<Message>, liked: bool) -> Message {
+    echoed.unwrap_or_else(|| {
+        let was_liked = message.liked.unwrap_or_default();
+        let likes = message.likes.unwrap_or_default();
+        Message {
+            liked: Some(liked),
+            likes: Some(match (was_liked, liked) {
+                (false, true) => likes + 1,
+                (true, false) => likes.saturating_sub(1),
+                _ => likes,
+            }),
+            ..message.clone()
+        }
+    })
+}
+/// `message` after flagging (or unflagging) it, for endpoints that don't echo the message.
+pub(crate) fn flagged_message(message: &Message, echoed: Option<Message>, flagged: bool) -> Message {
+    echoed.unwrap_or_else(|| Message {
+        flagged: Some(flagged),
+        ..message.clone()
+    })
+}
+/// The chat `message` was sent in.
+pub(crate) fn find_chat<'a>(chats: &'a [AnyChat], message: &Message) -> Option<&'a AnyChat> {
+    let (chat_type, id) = match (message.channel_id, message.conversation_id) {
+        (Some(id), _) if id != 0 => (ChatType::Channel, id),
+        (_, Some(id)) if id != 0 => (ChatType::Conversation, id),
+        _ => return None,
+    };
+    chats.iter().find(|chat| chat.chat_type() == chat_type && chat.id() == id.to_string())
+}
+/// Decrypts `message` with the keys of its chat, leaving it as it is if there are none.
+pub(crate) fn decrypt_if_possible(message: &mut Message, chats: &[AnyChat], encryption_state: &EncryptionState, key_ring: Option<&ChatKeyRing>) {
+    let Some(chat) = find_chat(chats, message) else {
+        return;
+    };
+    let keys = match key_ring {
+        Some(key_ring) => key_ring::known_keys(key_ring, encryption_state, chat),
+        None => encryption_state.chat_key(chat).map(|key| vec![(0, key)]),
+    };
+    if let Ok(keys) = keys {
+        // messages that don't decrypt are kept encrypted, like those without a key
+        let _ = key_ring::decrypt_with_keys(message, &keys);
+    }
+}
+
+/// A member who confirmed a message, see [`get_confirmation_report`].
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct MemberConfirmation {
+    pub member: DetailedPersonInfo,
+    /// Unix timestamp of the confirmation.
+    pub time: Option<u64>,
+}
+/// Who confirmed a message that requires confirmation, and who hasn't yet.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct ConfirmationReport {
+    /// Oldest confirmation first.
+    pub confirmed: Vec<MemberConfirmation>,
+    /// Members of the chat (except the sender) who haven't confirmed the message.
+    pub outstanding: Vec<DetailedPersonInfo>,
+}
+/// Parses timestamps the server sends either as numbers or as strings.
+pub(crate) fn parse_time(time: &Value) -> Option<u64> {
+    match time {
+        Value::Number(number) => number.as_u64(),
+        Value::String(string) => string.parse().ok(),
+        _ => None,
+    }
+}
+/// The `members` for which `is_done` doesn't hold, leaving out the sender of `message`.
+pub(crate) fn outstanding_members(members: Vec<DetailedPersonInfo>, message: &Message, is_done: impl Fn(&str) -> bool) -> Vec<DetailedPersonInfo> {
+    let sender = verification::sender_id(message);
+    members.into_iter()
+        .filter(|member| member.id.as_deref().is_some_and(|id| sender.as_deref() != Some(id) && !is_done(id)))
+        .collect()
+}
+/// The `members` who aren't in `seen`, leaving out the sender of `message`.
+pub fn unseen_members(members: Vec<DetailedPersonInfo>, seen: &[MessageSeen], message: &Message) -> Vec<DetailedPersonInfo> {
+    outstanding_members(members, message, |id| seen.iter().any(|seen| seen.user_id.as_deref() == Some(id)))
+}
+pub(crate) fn confirmation_report(confirmations: Vec<messages::ConfirmationData>, members: Vec<DetailedPersonInfo>, message: &Message) -> ConfirmationReport {
+    let mut confirmed = confirmations.into_iter()
+        .map(|confirmation| MemberConfirmation { time: parse_time(&confirmation.time), member: confirmation.user })
+        .collect::<Vec<_>>();
+    confirmed.sort_by_key(|confirmation| confirmation.time);
+    let outstanding = outstanding_members(members, message, |id| confirmed.iter().any(|confirmation| confirmation.member.id.as_deref() == Some(id)));
+    ConfirmationReport { confirmed, outstanding }
+}
+
+/// Replaces the text of one of our own messages in `chat`, re-encrypting it in encrypted chats.
+/// Returns the edited message, decrypted again.
+pub async fn edit_message(state: &State, chat: &impl Chat, encryption_state: Option<&EncryptionState>, message: &Message, text: impl ToString) -> Result<Message> {
+    let own_id = get_user_info(state).await?.id;
+    let prepared = prepare_edit(chat, encryption_state, message, &text.to_string(), &own_id)?;
+    let echoed = post_request::<messages::MessageChangeResponse>(state,
+                                                                 "/message/edit",
+                                                                 messages::EditMessageRequest::new(state, message.id.to_string(), prepared.text.clone(), prepared.encrypted, prepared.iv.clone(), prepared.verification.clone())?)
+        .await?.message;
+    edited_message(chat, encryption_state, message, echoed, prepared)
+}
+/// Deletes `message` from `chat` if [`may_delete_messages`] allows it to us under the `settings` of the chat's company.
+/// Returns the message with its `deleted` timestamp set.
+pub async fn delete_message(state: &State, chat: &AnyChat, settings: &CompanySettings, message: &Message) -> Result<Message> {
+    let own_id = get_user_info(state).await?.id;
+    if !may_delete_messages(chat, settings, message, &own_id) {
+        return Err(Errors::PermissionDenied(format!("deleting message {} in chat {}", message.id, chat.id())));
+    }
+    let echoed = post_request::<messages::MessageChangeResponse>(state,
+                                                                 "/message/delete",
+                                                                 messages::MessageIdRequest::new(state, message.id.to_string())?)
+        .await?.message;
+    Ok(deleted_message(message, echoed))
+}
+/// Likes `message` and returns it with the updated like count.
+pub async fn like_message(state: &State, message: &Message) -> Result<Message> {
+    let echoed = post_request::<messages::MessageChangeResponse>(state,
+                                                                 "/message/like",
+                                                                 messages::MessageIdRequest::new(state, message.id.to_string())?).await?
+        .message;
+    Ok(liked_message(message, echoed, true))
+}
+/// Takes back a like of `message` and returns it with the updated like count.
+pub async fn unlike_message(state: &State, message: &Message) -> Result<Message> {
+    let echoed = post_request::<messages::MessageChangeResponse>(state,
+                                                                 "/message/unlike",
+                                                                 messages::MessageIdRequest::new(state, message.id.to_string())?).await?
+        .message;
+    Ok(liked_message(message, echoed, false))
+}
+/// Flags `message` for ourselves.
+pub async fn flag_message(state: &State, message: &Message) -> Result<Message> {
+    let echoed = post_request::<messages::MessageChangeResponse>(state,
+                                                                 "/message/set_flag",
+                                                                 messages::MessageIdRequest::new(state, message.id.to_string())?).await?
+        .message;
+    Ok(flagged_message(message, echoed, true))
+}
+/// Removes our flag from `message`.
+pub async fn unflag_message(state: &State, message: &Message) -> Result<Message> {
+    let echoed = post_request::<messages::MessageChangeResponse>(state,
+                                                                 "/message/unset_flag",
+                                                                 messages::MessageIdRequest::new(state, message.id.to_string())?).await?
+        .message;
+    Ok(flagged_message(message, echoed, false))
+}
+/// Fetches the messages we flagged in all chats, page by page.
+/// With an `encryption_state`, messages are decrypted with the key of their chat (and older keys from `key_ring`)
+/// where one is available; the others are returned encrypted.
+pub async fn get_flagged_messages(state: &State, encryption_state: Option<&EncryptionState>, key_ring: Option<&ChatKeyRing>) -> Result<Vec<Message>> {
+    let mut flagged = vec![];
+    loop {
+        let page = post_request::<chats::MessageResponse>(state,
+                                                          "/message/list_flagged",
+                                                          messages::FlaggedMessagesRequest::new(state, PAGE_SIZE, flagged.len())?).await?
+            .messages;
+        let done = page.len() < PAGE_SIZE;
+        flagged.extend(page);
+        if done {
+            break;
+        }
+    }
+    for message in &mut flagged {
+        message.keep_originals();
+    }
+    let Some(encryption_state) = encryption_state else {
+        return Ok(flagged);
+    };
+    let chats = get_all_chats(state).await?;
+    for message in &mut flagged {
+        decrypt_if_possible(message, &chats, encryption_state, key_ring);
+    }
+    Ok(flagged)
+}
+/// Marks a single message as read.
+pub async fn mark_message_read(state: &State, message: &Message) -> Result<()> {
+    post_request::<Value>(state,
+                          "/message/mark_read",
+                          messages::MessageIdRequest::new(state, message.id.to_string())?).await?;
+    Ok(())
+}
+/// Marks `message` and every message before it in `chat` as read.
+pub async fn mark_read_until(state: &State, chat: &impl Chat, message: &Message) -> Result<()> {
+    post_request::<Value>(state,
+                          "/message/mark_chat_read",
+                          messages::MarkReadRequest::new(state, chat.chat_type().to_string(), chat.id().to_string(), Some(message.id.to_string()))?).await?;
+    Ok(())
+}
+/// Marks every message in `chat` as read.
+pub async fn mark_chat_read(state: &State, chat: &impl Chat) -> Result<()> {
+    post_request::<Value>(state,
+                          "/message/mark_chat_read",
+                          messages::MarkReadRequest::new(state, chat.chat_type().to_string(), chat.id().to_string(), None)?).await?;
+    Ok(())
+}
+/// Marks all channels of every company and all conversations with unread messages as read.
+/// Returns how many chats have been marked.
+pub async fn mark_all_read(state: &State) -> Result<usize> {
+    let mut marked = 0;
+    for chat in get_all_chats(state).await?.iter().filter(|chat| chat.unread() > 0) {
+        mark_chat_read(state, chat).await?;
+        marked += 1;
+    }
+    Ok(marked)
+}
+/// The members of `chat`, fetched for channels and taken from the chat object for conversations.
+pub async fn get_chat_members(state: &State, chat: &impl Chat) -> Result<Vec<DetailedPersonInfo>> {
+    match chat.known_members() {
+        Some(members) => Ok(members.to_vec()),
+        None => get_channel_members(state, chat.id()).await,
+    }
+}
+/// Confirms that we read `message`, which requires confirmation.
+pub async fn confirm_message(state: &State, message: &Message) -> Result<Message> {
+    let echoed = post_request::<messages::MessageChangeResponse>(state,
+                                                                 "/message/confirm",
+                                                                 messages::MessageIdRequest::new(state, message.id.to_string())?).await?
+        .message;
+    Ok(echoed.unwrap_or_else(|| message.clone()))
+}
+/// Lists who confirmed `message` in `chat` and when, as well as the members who haven't confirmed it yet.
+pub async fn get_confirmation_report(state: &State, chat: &impl Chat, message: &Message) -> Result<ConfirmationReport> {
+    let confirmations = post_request::<messages::ConfirmationsResponse>(state,
+                                                                        "/message/list_confirmations",
+                                                                        messages::MessageIdRequest::new(state, message.id.to_string())?).await?
+        .confirmations;
+    let members = get_chat_members(state, chat).await?;
+    Ok(confirmation_report(confirmations, members, message))
+}
+/// Fetches everyone who has seen `message`, oldest first.
+pub async fn get_seen_by(state: &State, message: &Message) -> Result<Vec<MessageSeen>> {
+    let mut seen = post_request::<messages::SeenResponse>(state,
+                                                          "/message/list_seen",
+                                                          messages::MessageIdRequest::new(state, message.id.to_string())?).await?
+        .seen;
+    seen.sort_by_key(|seen| seen.time);
+    Ok(seen)
+}
+/// The members of `channel` who haven't seen `message` yet.
+pub async fn get_unseen_members(state: &State, channel: &Channel, message: &Message) -> Result<Vec<DetailedPersonInfo>> {
+    let seen = get_seen_by(state, message).await?;
+    let members = get_channel_members(state, channel.id()).await?;
+    Ok(unseen_members(members, &seen, message))
+}
+/// Forwards `message` from `source` into `target`, refusing forwards that `options` or the company `settings` don't allow
+/// (see [`ForwardOptions`]).
+///
+/// Text, location and metainfo are decrypted with the key of `source` and encrypted again for `target`. Attached files are shared into `target`;
+/// if they are encrypted with a different key than the one of `target`, they are downloaded and uploaded again instead.
+/// Returns the forwarded message, decrypted.
+pub async fn forward_message(state: &State, source: &impl Chat, target: &impl Chat, encryption_state: Option<&EncryptionState>, settings: &CompanySettings, message: &Message, options: ForwardOptions) -> Result<Message> {
+    let forward = prepare_forward(source, target, encryption_state, settings, &options, message)?;
+    let mut files = vec![];
+    for file in forward.message.files.iter().flatten() {
+        let shared = if forward.can_share(file) {
+            share_file(state, file, target).await?
+        } else {
+            let data = download_file(state, forward.source_key.clone(), file.clone()).await?;
+            upload_file(state, target, forward.target_key.as_ref(), &file.name, data).await?
+        };
+        files.push(shared.id);
+    }
+    let (prepared, options) = prepare_forwarded_text(target, encryption_state, &forward, files)?;
+    let mut forwarded = post_request::<chats::SendMessageResponse>(state,
+                                                                   "/message/send",
+                                                                   chats::SendMessageRequest::new(state, target.chat_type().to_string(), target.id().to_string(), prepared, options)?)
+        .await?.message;
+    forwarded.keep_originals();
+    if let Some(key) = &forward.target_key {
+        forwarded.decrypt(key.expose())?;
+    }
+    Ok(forwarded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use crate::test_support::{channel, encryption_state, member, message};
+    use crate::types::chats::messages::{decrypt_hex, encrypt_hex};
+
+    #[test]
+    fn only_own_messages_can_be_edited() {
+        let chat = channel(json!({}));
+        assert!(prepare_edit(&chat, None, &message("42", json!({"text": "hi"})), "hello", "42").is_ok());
+        assert!(matches!(prepare_edit(&chat, None, &message("43", json!({"text": "hi"})), "hello", "42"),
+                         Err(Errors::PermissionDenied(_))));
+    }
+    #[test]
+    fn messages_sharing_their_iv_arent_edited_in_encrypted_chats() {
+        let location = json!({"text": "", "iv": "00".repeat(16), "location": {"latitude": "ab", "longitude": "cd"}});
+        assert!(matches!(prepare_edit(&channel(json!({"encrypted": true})), None, &message("42", location.clone()), "hello", "42"),
+                         Err(Errors::ValueError(_))));
+        let prepared = prepare_edit(&channel(json!({})), None, &message("42", location), "hello", "42").unwrap();
+        assert_eq!(prepared.text, "hello");
+    }
+    #[test]
+    fn forwards_keep_location_and_metainfo() {
+        let state = encryption_state();
+        let key = SecretBytes::new(vec![7; 32]);
+        let wrapped_key = BASE64.encode(state.encrypt_for(state.public_key(), key.expose()).unwrap());
+        let target = channel(json!({"encrypted": true, "key": wrapped_key}));
+        let forward = PreparedForward {
+            message: message("43", json!({"text": "", "location": {"latitude": 52.5, "longitude": 13.4}, "metainfo": "meta"})),
+            source_key: None,
+            target_key: Some(key.clone()),
+        };
+        let (prepared, options) = prepare_forwarded_text(&target, Some(&state), &forward, vec!["f1".to_string()]).unwrap();
+        assert!(options.is_forwarded);
+        let iv = prepared.iv.as_deref();
+        let decrypt = |data: Option<String>| decrypt_hex(key.expose(), iv, &data.unwrap()).unwrap();
+        assert_eq!(decrypt(prepared.latitude), "52.5");
+        assert_eq!(decrypt(prepared.longitude), "13.4");
+        assert_eq!(decrypt(prepared.metainfo), "meta");
+        assert_eq!(prepared.files, vec!["f1".to_string()]);
+    }
+    #[test]
+    fn likes_are_counted_once() {
+        let liked = liked_message(&message("42", json!({"likes": 2, "liked": false})), None, true);
+        assert_eq!((liked.liked, liked.likes), (Some(true), Some(3)));
+        assert_eq!(liked_message(&liked, None, true).likes, Some(3));
+        let unliked = liked_message(&liked, None, false);
+        assert_eq!((unliked.liked, unliked.likes), (Some(false), Some(2)));
+        assert_eq!(liked_message(&message("42", json!({"liked": true})), None, false).likes, Some(0));
+        // the server's answer wins
+        let echoed = message("42", json!({"likes": 5, "liked": true}));
+        assert_eq!(liked_message(&unliked, Some(echoed.clone()), true), echoed);
+    }
+    #[test]
+    fn flagging_keeps_the_message() {
+        let flagged = flagged_message(&message("42", json!({"text": "hi"})), None, true);
+        assert_eq!((flagged.flagged, flagged.text.as_deref()), (Some(true), Some("hi")));
+        assert_eq!(flagged_message(&flagged, None, false).flagged, Some(false));
+    }
+    #[test]
+    fn undecryptable_messages_are_kept() {
+        const KEY: [u8; 32] = [7; 32];
+        let state = encryption_state();
+        let wrapped_key = BASE64.encode(state.encrypt(KEY.to_vec()).unwrap());
+        let chats = [AnyChat::from(channel(json!({"encrypted": true, "key": wrapped_key})))];
+        let iv = [1; 16];
+        let encrypted = |key: &[u8], channel_id: u64| message("42", json!({
+            "channel_id": channel_id,
+            "encrypted": true,
+            "iv": hex::encode(iv),
+            "text": encrypt_hex(key, &iv, "hello").unwrap(),
+        }));
+
+        let mut readable = encrypted(&KEY, 1);
+        decrypt_if_possible(&mut readable, &chats, &state, None);
+        assert_eq!(readable.text.as_deref(), Some("hello"));
+        // wrong key, unknown chat
+        for original in [encrypted(&[8; 32], 1), encrypted(&KEY, 2)] {
+            let mut message = original.clone();
+            decrypt_if_possible(&mut message, &chats, &state, None);
+            assert_eq!(message, original);
+        }
+    }
+    #[test]
+    fn confirmations_are_sorted_and_the_rest_is_outstanding() {
+        let confirmation = |id: &str, time: Value| messages::ConfirmationData { user: member(id), time };
+        let members = ["1", "2", "3", "42"].map(member).to_vec();
+        let report = confirmation_report(vec![confirmation("2", json!("200")), confirmation("1", json!(100))], members, &message("42", json!({})));
+        assert_eq!(report.confirmed.iter().map(|confirmed| (confirmed.member.id.as_deref(), confirmed.time)).collect::<Vec<_>>(),
+                   [(Some("1"), Some(100)), (Some("2"), Some(200))]);
+        // the sender doesn't have to confirm their own message
+        assert_eq!(report.outstanding, [member("3")]);
+        assert_eq!(parse_time(&json!(null)), None);
+    }
+    #[test]
+    fn unseen_members_leave_out_the_sender() {
+        let seen = |id: &str| MessageSeen { user_id: Some(id.to_string()), first_name: None, last_name: None, time: 100 };
+        let members = ["1", "2", "3", "42"].map(member).to_vec();
+        let message = message("42", json!({}));
+        assert_eq!(unseen_members(members.clone(), &[seen("2")], &message), [member("1"), member("3")]);
+        assert_eq!(unseen_members(members.clone(), &[seen("1"), seen("2"), seen("3")], &message), []);
+        assert_eq!(unseen_members(members, &[], &message).len(), 3);
+    }
+}
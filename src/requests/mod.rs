@@ -7,6 +7,8 @@ use crate::request_types::APIResponse;
 pub mod login;
 pub mod about;
 pub mod chats;
+pub mod keys;
+pub mod messages;
 #[cfg(feature = "blocking")]
 pub mod blocking;
 
@@ -18,7 +20,23 @@ pub async fn post_request<T: DeserializeOwned>(state: &State, path: impl ToStrin
         .header("Accept", "application/json")
         .header("User-Agent", "Mozilla/5.0 (X11; Linux x86_64; rv:133.0) Gecko/20100101 Firefox/133.0")
         .send().await?
-        .json::<APIResponse>().await.map_err(|e| Errors::NotJsonError(e))?;
+        .json::<APIResponse>().await.map_err(Errors::NotJsonError)?;
+    parse_payload(response)
+}
+/// Like [`post_request`], but sends `form` as `multipart/form-data`, e.g. to upload files.
+pub async fn multipart_request<T: DeserializeOwned>(state: &State, path: impl ToString, form: reqwest::multipart::Form) -> Result<T> {
+    let url = state.build_url(path);
+    let response = reqwest::Client::new()
+        .post(url)
+        .multipart(form)
+        .header("Accept", "application/json")
+        .header("User-Agent", "Mozilla/5.0 (X11; Linux x86_64; rv:133.0) Gecko/20100101 Firefox/133.0")
+        .send().await?
+        .json::<APIResponse>().await.map_err(Errors::NotJsonError)?;
+    parse_payload(response)
+}
+/// Checks the status of an API response and deserializes its payload.
+pub(crate) fn parse_payload<T: DeserializeOwned>(response: APIResponse) -> Result<T> {
     if response.status.value != "OK" {
         return Err(Errors::APIError(response.status.value, response.status.short_message, response.status.message))
     }
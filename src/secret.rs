@@ -0,0 +1,130 @@
+use std::fmt::{Debug, Formatter};
+use zeroize::Zeroize;
+
+/// Secret bytes (keys, decrypted key material) that are zeroed when dropped and never printed.
+/// Comparisons take the same time wherever the bytes differ.
+#[derive(Clone, Default)]
+pub struct SecretBytes(Vec<u8>);
+impl SecretBytes {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+    /// `len` zero bytes, e.g. as an output buffer for key derivation.
+    pub fn zeroed(len: usize) -> Self {
+        Self(vec![0; len])
+    }
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+    pub fn expose_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+    /// Shortens the secret to `len` bytes, e.g. after decrypting into a buffer sized for the ciphertext.
+    pub fn truncate(&mut self, len: usize) {
+        let len = len.min(self.0.len());
+        self.0[len..].zeroize();
+        self.0.truncate(len);
+    }
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+impl From<Vec<u8>> for SecretBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+impl AsRef<[u8]> for SecretBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+impl PartialEq for SecretBytes {
+    fn eq(&self, other: &Self) -> bool {
+        constant_time_eq(&self.0, &other.0)
+    }
+}
+impl Eq for SecretBytes {}
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+impl Debug for SecretBytes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SecretBytes([REDACTED; {} bytes])", self.0.len())
+    }
+}
+
+/// Secret text (passphrases, decrypted JWKs) that is zeroed when dropped and never printed.
+/// Comparisons take the same time wherever the text differs.
+#[derive(Clone, Default)]
+pub struct SecretString(String);
+impl SecretString {
+    pub fn new(string: String) -> Self {
+        Self(string)
+    }
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+impl From<String> for SecretString {
+    fn from(string: String) -> Self {
+        Self(string)
+    }
+}
+impl From<&str> for SecretString {
+    fn from(string: &str) -> Self {
+        Self(string.to_string())
+    }
+}
+impl PartialEq for SecretString {
+    fn eq(&self, other: &Self) -> bool {
+        constant_time_eq(self.0.as_bytes(), other.0.as_bytes())
+    }
+}
+impl Eq for SecretString {}
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+impl Debug for SecretString {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SecretString([REDACTED])")
+    }
+}
+
+/// Compares all bytes instead of stopping at the first difference, so the time only depends on the lengths.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let difference = a.iter().zip(b).fold(0, |difference, (a, b)| difference | (a ^ b));
+    std::hint::black_box(difference) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_compare_by_content() {
+        assert_eq!(SecretBytes::new(vec![1, 2, 3]), SecretBytes::new(vec![1, 2, 3]));
+        assert_ne!(SecretBytes::new(vec![1, 2, 3]), SecretBytes::new(vec![1, 2, 4]));
+        assert_ne!(SecretBytes::new(vec![1, 2, 3]), SecretBytes::new(vec![1, 2]));
+        assert_eq!(SecretString::from("passphrase"), SecretString::from("passphrase"));
+        assert_ne!(SecretString::from("passphrase"), SecretString::from("Passphrase"));
+    }
+    #[test]
+    fn truncate_keeps_the_start() {
+        let mut secret = SecretBytes::new(vec![1, 2, 3, 4]);
+        secret.truncate(2);
+        assert_eq!(secret.expose(), [1, 2]);
+        secret.truncate(5);
+        assert_eq!(secret.len(), 2);
+    }
+}
@@ -1,48 +1,243 @@
 #![allow(dead_code)]
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD as BASE64;
-use openssl::pkey::{PKey, Private, Public};
-use openssl::rsa::{Padding, Rsa};
 use crate::Result;
-use rand::distr::Alphanumeric;
-use rand::Rng;
+use std::fmt::{Debug, Formatter};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::crypto::{EncryptionFunction, Prf, PrivateKey, PublicKey};
+use crate::crypto;
 use crate::errors::Errors;
+use crate::key_store::fingerprint;
+use crate::request_types::about::{EncryptedPrivateKeyData, KeyDerivationProperties, PrivateKeyData};
+pub use crate::request_types::about::{RSAPrivateKey, RSAPublicKey};
+use crate::secret::{SecretBytes, SecretString};
+use crate::types::chats::Chat;
+use crate::verification::{ContactKeys, SignedContent, TrustPolicy};
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct EncryptionState {
-    private_key: Rsa<Private>,
-    public_key: Rsa<Public>,
-    private_signing_key: Rsa<Private>,
-    public_signing_key: Rsa<Public>,
+    private_key: PrivateKey,
+    public_key: PublicKey,
+    private_signing_key: PrivateKey,
+    public_signing_key: PublicKey,
 }
 impl EncryptionState {
-    pub fn new(private_encryption_key: Rsa<Private>, public_encryption_key: Rsa<Public>, private_signing_key: Rsa<Private>, public_signing_key: Rsa<Public>) -> Self {
+    pub fn new(private_encryption_key: impl Into<PrivateKey>, public_encryption_key: impl Into<PublicKey>, private_signing_key: impl Into<PrivateKey>, public_signing_key: impl Into<PublicKey>) -> Self {
         Self {
-            private_key: private_encryption_key,
-            public_key: public_encryption_key,
-            private_signing_key,
-            public_signing_key
+            private_key: private_encryption_key.into(),
+            public_key: public_encryption_key.into(),
+            private_signing_key: private_signing_key.into(),
+            public_signing_key: public_signing_key.into(),
         }
     }
-    pub fn decrypt(&self, key: String) -> Result<Vec<u8>> {
-        let encrypted_data = BASE64.decode(key).map_err(|e| Errors::Base64Error(e))?;
-        let mut result = vec![0; encrypted_data.len()];
-        let decrypted_len = self.private_key.private_decrypt(&*encrypted_data, &mut *result, Padding::PKCS1_OAEP).map_err(|e| Errors::EncryptionError(e))?;
-        result.truncate(decrypted_len);
-        Ok(result)
+    /// Unlocks the private keys as returned by `/security/get_private_key`.
+    /// The encryption key is protected by the passphrase, the signing key by a KEK encrypted with the encryption key.
+    pub(crate) fn unlock(encryption: &EncryptedKeyPair, signing: &EncryptedKeyPair, passphrase: &SecretString) -> Result<Self> {
+        // private key info is stored as a string containing JSON data.
+        let encrypted_private_key_info: EncryptedPrivateKeyData = serde_json::from_str(&encryption.private_key)?;
+        let cipher = encrypted_private_key_info.encryption_algorithm()?;
+
+        // derive key decryption AES key
+        let derivation_properties = encrypted_private_key_info.key_derivation_properties.as_ref()
+            .ok_or(Errors::ValueError("API didn't respond with key derivation properties".to_string()))?;
+        let prf = derivation_properties.prf_algorithm()?;
+        let mut derived_key = SecretBytes::zeroed(cipher.key_len());
+        let salt = BASE64.decode(&derivation_properties.salt)?;
+        crypto::pbkdf2(prf, passphrase.expose().as_bytes(), &salt, derivation_properties.iterations, derived_key.expose_mut())?;
+
+        // load the encryption keys
+        let iv = BASE64.decode(&encrypted_private_key_info.iv)?;
+        let encrypted_private_encrypt = BASE64.decode(&encrypted_private_key_info.ciphertext)?;
+        // CBC has no authentication, so a wrong passphrase shows as bad padding or garbage that isn't a key
+        let private_encrypt = crypto::symmetric_decrypt(cipher, derived_key.expose(), Some(&iv), &encrypted_private_encrypt)
+            .map(SecretBytes::new)
+            .and_then(|decrypted| RSAPrivateKey::from_decrypted(decrypted.expose().to_vec())?.to_key())
+            .map_err(|_| Errors::WrongPassphrase)?;
+        let public_encrypt = encryption.public_key.parse::<RSAPublicKey>()?.to_key()?;
+
+        // load signing key
+        let encrypted_sing: EncryptedPrivateKeyData = serde_json::from_str(&signing.private_key).map_err(Errors::JsonDeserializeError)?;
+        let cipher = encrypted_sing.encryption_algorithm()?;
+        let encrypted_kek = encrypted_sing.encryptedKEK.as_ref().ok_or(Errors::ValueError("No Key Encryption Key (KEK)".to_string()))?;
+        let encrypted_kek = BASE64.decode(encrypted_kek)?;
+
+        // decrypt kek
+        let decrypted_kek = crypto::rsa_decrypt(&private_encrypt, &encrypted_kek)?;
+        if decrypted_kek.len() != cipher.key_len() {
+            return Err(Errors::ValueError(format!("decrypted KEK is not {} bits long", cipher.key_len() * 8)));
+        };
+
+        //decrypt RSA key
+        let iv = BASE64.decode(&encrypted_sing.iv)?;
+        let encrypted_signing_key = BASE64.decode(&encrypted_sing.ciphertext)?;
+        let decrypted_signing_key = SecretBytes::new(crypto::symmetric_decrypt(cipher, decrypted_kek.expose(), Some(&iv), &encrypted_signing_key)?);
+        let private_sign= RSAPrivateKey::from_decrypted(decrypted_signing_key.expose().to_vec())?.to_key()?;
+        let public_sign = signing.public_key.parse::<RSAPublicKey>()?.to_key()?;
+        Ok(Self::new(private_encrypt, public_encrypt, private_sign, public_sign))
+    }
+    /// Protects the keys with `passphrase` in the same JWK + PBKDF2 envelope the server uses,
+    /// so they can be restored with [`EncryptionState::import`] without talking to the server.
+    pub fn export(&self, passphrase: impl Into<SecretString>) -> Result<String> {
+        let passphrase = passphrase.into();
+        // encryption key, protected by the passphrase
+        let mut salt = vec![0; 16];
+        crypto::random_bytes(&mut salt)?;
+        let mut derived_key = SecretBytes::zeroed(EXPORT_ENCRYPTION_FUNC.key_len());
+        crypto::pbkdf2(EXPORT_PRF, passphrase.expose().as_bytes(), &salt, EXPORT_ITERATIONS, derived_key.expose_mut())?;
+        let private_encrypt = RSAPrivateKey::from_key(&self.private_key)?.to_jwk()?;
+        let (iv, ciphertext) = encrypt_aes(derived_key.expose(), private_encrypt.expose().as_bytes())?;
+        let encryption = EncryptedPrivateKeyData {
+            iv,
+            ciphertext,
+            encryption_func: EXPORT_ENCRYPTION_FUNC.to_string(),
+            key_derivation_properties: Some(KeyDerivationProperties {
+                prf: EXPORT_PRF.to_string(),
+                iterations: EXPORT_ITERATIONS,
+                salt: BASE64.encode(salt),
+            }),
+            encryptedKEK: None,
+        };
+
+        // signing key, protected by a KEK that is encrypted for the encryption key
+        let mut kek = SecretBytes::zeroed(EXPORT_ENCRYPTION_FUNC.key_len());
+        crypto::random_bytes(kek.expose_mut())?;
+        let private_sign = RSAPrivateKey::from_key(&self.private_signing_key)?.to_jwk()?;
+        let (iv, ciphertext) = encrypt_aes(kek.expose(), private_sign.expose().as_bytes())?;
+        let signing = EncryptedPrivateKeyData {
+            iv,
+            ciphertext,
+            encryption_func: EXPORT_ENCRYPTION_FUNC.to_string(),
+            key_derivation_properties: None,
+            encryptedKEK: Some(BASE64.encode(self.encrypt(kek.expose().to_vec())?)),
+        };
+
+        Ok(serde_json::to_string_pretty(&ExportedKeys {
+            encryption: EncryptedKeyPair {
+                private_key: serde_json::to_string(&encryption)?,
+                public_key: RSAPublicKey::from_key(&self.public_key).to_jwk()?,
+            },
+            signing: EncryptedKeyPair {
+                private_key: serde_json::to_string(&signing)?,
+                public_key: RSAPublicKey::from_key(&self.public_signing_key).to_jwk()?,
+            },
+        })?)
+    }
+    /// Restores keys exported with [`EncryptionState::export`], failing with [`Errors::WrongPassphrase`] if `passphrase` doesn't match.
+    pub fn import(exported: &str, passphrase: impl Into<SecretString>) -> Result<Self> {
+        let exported: ExportedKeys = serde_json::from_str(exported)?;
+        Self::unlock(&exported.encryption, &exported.signing, &passphrase.into())
+    }
+    /// [`EncryptionState::export`]s the keys into the file at `path`, only readable by the current user on unix.
+    pub fn export_to_file(&self, path: impl AsRef<Path>, passphrase: impl Into<SecretString>) -> Result<()> {
+        let path = path.as_ref();
+        let exported = self.export(passphrase)?;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(path)
+            .and_then(|mut file| file.write_all(exported.as_bytes()))
+            .map_err(|e| Errors::OtherErrors(format!("couldn't write keys to {}: {e}", path.display())))
+    }
+    /// [`EncryptionState::import`]s the keys from the file at `path`.
+    pub fn import_from_file(path: impl AsRef<Path>, passphrase: impl Into<SecretString>) -> Result<Self> {
+        let path = path.as_ref();
+        let exported = fs::read_to_string(path)
+            .map_err(|e| Errors::OtherErrors(format!("couldn't read keys from {}: {e}", path.display())))?;
+        Self::import(&exported, passphrase)
+    }
+    pub fn decrypt(&self, key: String) -> Result<SecretBytes> {
+        let encrypted_data = BASE64.decode(key)?;
+        crypto::rsa_decrypt(&self.private_key, &encrypted_data)
     }
+    /// Encrypts `data` for ourselves, so that [`EncryptionState::decrypt`] can read it again.
     pub fn encrypt(&self, data: Vec<u8>) -> Result<Vec<u8>> {
-        let mut result = vec![0; self.private_key.size() as usize];
-        let encrypted_len = self.private_key.private_encrypt(&*data, &mut *result, Padding::PKCS1_OAEP).map_err(|e| Errors::EncryptionError(e))?;
-        result.truncate(encrypted_len);
-        Ok(result)
+        self.encrypt_for(&self.public_key, data)
     }
-    #[cfg(feature = "experimental")]
-    pub fn sign(&self, data: Vec<u8>) -> Result<String> {
-        let key = PKey::from_rsa(self.private_key.clone())?;
-        let mut signer = openssl::sign::Signer::new(openssl::hash::MessageDigest::sha256(), &key)?;
-        signer.update(&*data)?;
-        Ok(BASE64.encode(signer.sign_to_vec()?))
+    /// Encrypts `data` (RSA-OAEP) for the owner of `public_key`, e.g. a chat key for another member.
+    pub fn encrypt_for(&self, public_key: &PublicKey, data: impl AsRef<[u8]>) -> Result<Vec<u8>> {
+        crypto::rsa_encrypt(public_key, data.as_ref())
+    }
+    /// Decrypts the AES-256 key of a [`Channel`](crate::types::chats::channels::Channel) or
+    /// [`Conversation`](crate::types::chats::conversations::Conversation).
+    pub fn chat_key(&self, chat: &impl Chat) -> Result<SecretBytes> {
+        let Some(key) = chat.key() else {
+            return Err(if chat.key_requested() {
+                Errors::ChatKeyRequested(chat.id().to_string())
+            } else {
+                Errors::MissingChatKey(chat.id().to_string())
+            });
+        };
+        let cipher = chat.cipher()?;
+        let key = self.decrypt(key.to_string())?;
+        if key.len() != cipher.key_len() {
+            return Err(Errors::ValueError(format!("decrypted chat key is not {} bits long", cipher.key_len() * 8)));
+        }
+        Ok(key)
+    }
+    /// Encrypts `data` for a contact, refusing keys that don't satisfy `policy` (see [`ContactKeys::verify`]).
+    pub fn encrypt_for_contact(&self, contact: &ContactKeys, ca_key: &PublicKey, policy: TrustPolicy, data: impl AsRef<[u8]>) -> Result<Vec<u8>> {
+        self.encrypt_for(&contact.trusted_encryption_key(ca_key, policy)?, data)
+    }
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+    pub fn public_signing_key(&self) -> &PublicKey {
+        &self.public_signing_key
+    }
+    /// Signs `data` (RSASSA-PKCS1-v1_5 with SHA-256) with our private signing key.
+    pub fn sign(&self, data: impl AsRef<[u8]>) -> Result<Vec<u8>> {
+        crypto::rsa_sign(&self.private_signing_key, data.as_ref())
+    }
+    /// Produces the hex encoded signature of an outgoing message, as sent in its `verification` field.
+    /// Outgoing messages are only signed with the `experimental` feature, see [`SignedContent`].
+    pub fn sign_message(&self, content: &SignedContent) -> Result<String> {
+        Ok(hex::encode(self.sign(content.to_bytes())?))
+    }
+}
+const EXPORT_ITERATIONS: usize = 100_000;
+const EXPORT_PRF: Prf = Prf::HmacSha256;
+const EXPORT_ENCRYPTION_FUNC: EncryptionFunction = EncryptionFunction::Aes256Cbc;
+
+/// A private key (as an [`EncryptedPrivateKeyData`] JSON string) and its public key (JWK).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct EncryptedKeyPair {
+    pub(crate) private_key: String,
+    pub(crate) public_key: String,
+}
+impl From<&PrivateKeyData> for EncryptedKeyPair {
+    fn from(data: &PrivateKeyData) -> Self {
+        Self {
+            private_key: data.private_key.clone(),
+            public_key: data.public_key.clone(),
+        }
+    }
+}
+/// File format of [`EncryptionState::export`].
+#[derive(Serialize, Deserialize)]
+struct ExportedKeys {
+    encryption: EncryptedKeyPair,
+    signing: EncryptedKeyPair,
+}
+/// AES-256-CBC with a random IV, returning the base64 encoded IV and ciphertext.
+fn encrypt_aes(key: &[u8], data: &[u8]) -> Result<(String, String)> {
+    let mut iv = vec![0; 16];
+    crypto::random_bytes(&mut iv)?;
+    let ciphertext = crypto::symmetric_encrypt(EXPORT_ENCRYPTION_FUNC, key, Some(&iv), data)?;
+    Ok((BASE64.encode(iv), BASE64.encode(ciphertext)))
+}
+
+/// Only shows fingerprints of the public keys, so private keys never end up in logs.
+impl Debug for EncryptionState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let fingerprint = |key: &PublicKey| fingerprint(key).unwrap_or_else(|_| "<invalid>".to_string());
+        f.debug_struct("EncryptionState")
+            .field("public_key", &fingerprint(&self.public_key))
+            .field("public_signing_key", &fingerprint(&self.public_signing_key))
+            .finish_non_exhaustive()
     }
 }
 #[derive(Clone, Debug)]
@@ -84,8 +279,155 @@ impl Default for State {
     fn default() -> Self {
         Self {
             base_url: "https://api.stashcat.com/".into(),
-            device_id: rand::rng().sample_iter(&Alphanumeric).take(32).map(char::from).collect(),
+            device_id: random_device_id(),
             client_key: None,
         }
     }
-}
\ No newline at end of file
+}
+/// 32 random alphanumeric characters, drawn from the crypto backend's random number generator
+/// (`getrandom`, i.e. `crypto.getRandomValues` in the browser, for the RustCrypto backend).
+fn random_device_id() -> String {
+    const ALPHANUMERIC: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+    let mut device_id = String::with_capacity(32);
+    let mut buffer = [0; 64];
+    while device_id.len() < 32 {
+        crypto::random_bytes(&mut buffer).expect("no random number generator available");
+        let missing = 32 - device_id.len();
+        // rejecting the bytes above the largest multiple of 62 keeps the characters uniformly distributed
+        device_id.extend(buffer.iter()
+            .filter(|byte| (**byte as usize) < ALPHANUMERIC.len() * 4)
+            .map(|byte| ALPHANUMERIC[*byte as usize % ALPHANUMERIC.len()] as char)
+            .take(missing));
+    }
+    device_id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::test_support::{channel, encryption_state, private_key, CA_KEY};
+
+    #[test]
+    fn encrypt_for_round_trips_with_decrypt() {
+        let state = encryption_state();
+        let encrypted = state.encrypt_for(state.public_key(), b"chat key").unwrap();
+        assert_eq!(state.decrypt(BASE64.encode(&encrypted)).unwrap().expose(), b"chat key");
+        // OAEP is randomized
+        assert_ne!(state.encrypt_for(state.public_key(), b"chat key").unwrap(), encrypted);
+    }
+    #[test]
+    fn encrypt_for_other_key_is_unreadable() {
+        let state = encryption_state();
+        let other = private_key(CA_KEY).public_key().unwrap();
+        let encrypted = state.encrypt_for(&other, b"chat key").unwrap();
+        assert!(state.decrypt(BASE64.encode(encrypted)).is_err());
+    }
+    #[test]
+    fn chat_key_is_unwrapped() {
+        let state = encryption_state();
+        let wrapped = BASE64.encode(state.encrypt(vec![7; 32]).unwrap());
+        let chat = channel(json!({"encrypted": true, "key": wrapped}));
+        assert_eq!(state.chat_key(&chat).unwrap().expose(), [7; 32]);
+    }
+    #[test]
+    fn missing_chat_key() {
+        let chat = channel(json!({"encrypted": true, "key": ""}));
+        assert!(matches!(encryption_state().chat_key(&chat), Err(Errors::MissingChatKey(id)) if id == "1"));
+    }
+    #[test]
+    fn requested_chat_key() {
+        let chat = channel(json!({"encrypted": true, "key_requested": "1"}));
+        assert!(matches!(encryption_state().chat_key(&chat), Err(Errors::ChatKeyRequested(id)) if id == "1"));
+    }
+    #[test]
+    fn chat_key_of_wrong_length() {
+        let state = encryption_state();
+        let wrapped = BASE64.encode(state.encrypt(vec![7; 16]).unwrap());
+        let chat = channel(json!({"encrypted": true, "key": wrapped}));
+        assert!(matches!(state.chat_key(&chat), Err(Errors::ValueError(_))));
+    }
+    #[test]
+    fn exported_keys_round_trip_through_a_file() {
+        let state = encryption_state();
+        let path = std::env::temp_dir().join(format!("exported_keys_test_{}.json", std::process::id()));
+        state.export_to_file(&path, "passphrase").unwrap();
+        #[cfg(unix)]
+        assert_eq!(std::os::unix::fs::PermissionsExt::mode(&fs::metadata(&path).unwrap().permissions()) & 0o777, 0o600);
+        let imported = EncryptionState::import_from_file(&path, "passphrase").unwrap();
+        fs::remove_file(path).unwrap();
+        assert!(imported.public_key() == state.public_key() && imported.public_signing_key() == state.public_signing_key());
+        let encrypted = state.encrypt_for(imported.public_key(), b"chat key").unwrap();
+        assert_eq!(imported.decrypt(BASE64.encode(encrypted)).unwrap().expose(), b"chat key");
+        assert_eq!(imported.sign(b"hello").unwrap(), state.sign(b"hello").unwrap());
+    }
+    #[test]
+    fn import_with_wrong_passphrase_fails() {
+        let exported = encryption_state().export("passphrase").unwrap();
+        assert!(matches!(EncryptionState::import(&exported, "wrong passphrase"), Err(Errors::WrongPassphrase)));
+    }
+
+    #[cfg(feature = "experimental")]
+    mod signing {
+        use super::*;
+        use crate::test_support::message;
+        use crate::types::chats::messages::Message;
+        use crate::verification::{verify_with_key, VerificationResult};
+
+        fn signed_message(state: &EncryptionState, content: &SignedContent, hash: Option<String>) -> Message {
+            message("42", json!({
+                "text": content.text,
+                "iv": content.iv,
+                "files": [],
+                "hash": hash,
+                "verification": state.sign_message(content).unwrap(),
+            }))
+        }
+        #[test]
+        fn signed_message_verifies() {
+            let state = encryption_state();
+            let content = SignedContent { text: "hello".to_string(), ..SignedContent::default() };
+            let message = signed_message(&state, &content, None);
+            assert_eq!(verify_with_key(&message, state.public_signing_key()).unwrap(), VerificationResult::Valid);
+            let other = private_key(CA_KEY).public_key().unwrap();
+            assert_eq!(verify_with_key(&message, &other).unwrap(), VerificationResult::Invalid);
+        }
+        #[test]
+        fn tampered_message_is_invalid() {
+            let state = encryption_state();
+            let content = SignedContent { text: "hello".to_string(), ..SignedContent::default() };
+            let mut message = signed_message(&state, &content, None);
+            message.original_text = Some("hello!".to_string());
+            assert_eq!(verify_with_key(&message, state.public_signing_key()).unwrap(), VerificationResult::Invalid);
+        }
+        /// We don't send a `hash`, but the server may echo one; it has to match the signed content.
+        #[test]
+        fn echoed_hash_is_checked() {
+            let state = encryption_state();
+            let content = SignedContent { text: "hello".to_string(), iv: Some("00".repeat(16)), ..SignedContent::default() };
+            let matching = signed_message(&state, &content, Some(content.hash().unwrap()));
+            assert_eq!(verify_with_key(&matching, state.public_signing_key()).unwrap(), VerificationResult::Valid);
+            let other_hash = SignedContent { text: "other".to_string(), ..SignedContent::default() }.hash().unwrap();
+            let mismatched = signed_message(&state, &content, Some(other_hash));
+            assert_eq!(verify_with_key(&mismatched, state.public_signing_key()).unwrap(), VerificationResult::Invalid);
+            let garbage = signed_message(&state, &content, Some("not base64".to_string()));
+            assert_eq!(verify_with_key(&garbage, state.public_signing_key()).unwrap(), VerificationResult::Invalid);
+        }
+        #[test]
+        fn unsigned_message_is_unsigned() {
+            let state = encryption_state();
+            let message = message("42", json!({"text": "hello"}));
+            assert_eq!(verify_with_key(&message, state.public_signing_key()).unwrap(), VerificationResult::Unsigned);
+        }
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    fn default_device_id_is_random_and_alphanumeric() {
+        let first = State::default().device_id;
+        let second = State::default().device_id;
+        assert_eq!(first.len(), 32);
+        assert!(first.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(first, second);
+    }
+}
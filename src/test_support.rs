@@ -0,0 +1,100 @@
+//! Fixtures shared by the unit tests. The keys in `testdata/` are throwaway keys generated for the tests.
+use crate::crypto::PrivateKey;
+use crate::state::EncryptionState;
+use crate::types::chats::channels::Channel;
+use crate::types::chats::messages::{File, Message};
+use crate::types::DetailedPersonInfo;
+
+pub(crate) const ENCRYPTION_KEY: &str = include_str!("../testdata/encryption_key.pem");
+pub(crate) const SIGNING_KEY: &str = include_str!("../testdata/signing_key.pem");
+pub(crate) const CA_KEY: &str = include_str!("../testdata/ca_key.pem");
+
+pub(crate) fn private_key(pem: &str) -> PrivateKey {
+    PrivateKey::from_pem(pem).unwrap()
+}
+/// An [`EncryptionState`] holding the `testdata/` encryption and signing keys.
+pub(crate) fn encryption_state() -> EncryptionState {
+    let encryption_key = private_key(ENCRYPTION_KEY);
+    let signing_key = private_key(SIGNING_KEY);
+    EncryptionState::new(encryption_key.clone(), encryption_key.public_key().unwrap(), signing_key.clone(), signing_key.public_key().unwrap())
+}
+/// A message with id 1 from user `sender_id`, built from the minimal JSON the server sends.
+/// `fields` are merged over the defaults, e.g. `json!({"text": "hi", "verification": "…"})`.
+pub(crate) fn message(sender_id: &str, fields: serde_json::Value) -> Message {
+    let mut message = serde_json::json!({
+        "id": 1,
+        "broadcast": false,
+        "alarm": false,
+        "confirmation_required": false,
+        "confirmations": [],
+        "sender": person(sender_id),
+    });
+    merge(&mut message, fields);
+    serde_json::from_value(message).unwrap()
+}
+/// An unencrypted file owned by user 42, with `fields` merged over the defaults like in [`message`].
+pub(crate) fn file(id: &str, fields: serde_json::Value) -> File {
+    let mut file = serde_json::json!({
+        "id": id,
+        "name": "file.txt",
+        "encrypted": false,
+        "dimensions": {},
+        "owner": person("42"),
+    });
+    merge(&mut file, fields);
+    serde_json::from_value(file).unwrap()
+}
+/// A chat member with `id`, named like the senders of [`message`].
+pub(crate) fn member(id: &str) -> DetailedPersonInfo {
+    serde_json::from_value(person(id)).unwrap()
+}
+fn person(id: &str) -> serde_json::Value {
+    serde_json::json!({
+        "id": id,
+        "first_name": "Test",
+        "last_name": "User",
+        "allows_voip_calls": false,
+        "federated": false,
+        "online": false,
+    })
+}
+fn merge(value: &mut serde_json::Value, fields: serde_json::Value) {
+    for (field, field_value) in fields.as_object().expect("fields must be an object") {
+        value[field] = field_value.clone();
+    }
+}
+/// A channel with id 1 that we are a member of, with `fields` merged over the defaults like in [`message`].
+pub(crate) fn channel(fields: serde_json::Value) -> Channel {
+    let mut channel = serde_json::json!({
+        "id": "1",
+        "name": "test",
+        "visible": true,
+        "encrypted": false,
+        "can_leave": true,
+        "show_membership_activities": false,
+        "show_activities": false,
+        "user_count": 2,
+        "pending_count": 0,
+        "request_count": 0,
+        "num_members_without_keys": 0,
+        "manager": false,
+        "writable": "all",
+        "inviteable": "all",
+        "membership": {
+            "is_member": true,
+            "invited_at": null,
+            "invited_by": null,
+            "invited_by_mx_user_id": null,
+            "joined": "",
+            "confirmation": "",
+            "may_manage": false,
+            "write": true,
+            "muted": null,
+        },
+        "favorite": false,
+        "unread": 0,
+        "federated": false,
+    });
+    merge(&mut channel, fields);
+    serde_json::from_value(channel).unwrap()
+}
@@ -1,6 +1,11 @@
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+use crate::crypto::{self, EncryptionFunction};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use crate::errors::Errors;
 use crate::types::DetailedPersonInfo;
+use crate::Result;
 
 #[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq)]
 pub struct Message {
@@ -43,6 +48,150 @@ pub struct Message {
     pub reply_to: Option<PossibleReply>,
     /// maybe usefull?
     pub original_text: Option<String>,
+    /// `location` as it was received, before decryption.
+    pub original_location: Option<MessageLocation>,
+    /// `metainfo` as it was received, before decryption.
+    pub original_metainfo: Option<String>,
+    /// `messagePayload` as it was received, before decryption.
+    pub original_payload: Option<Value>,
+}
+impl Message {
+    /// The id of the thread the message belongs to; a message without `thread_id` starts its own thread.
+    pub fn thread_root(&self) -> u64 {
+        self.thread_id.unwrap_or(self.id)
+    }
+    /// Latitude and longitude in degrees if this is a location message whose coordinates are readable,
+    /// i.e. not still encrypted.
+    pub fn coordinates(&self) -> Option<(f64, f64)> {
+        let location = self.location.as_ref()?;
+        // encrypted coordinates are hex strings until decrypted, only plain messages may carry degrees as strings
+        let degrees = |coordinate: &Coordinate| match coordinate {
+            Coordinate::Degrees(degrees) => Some(*degrees),
+            Coordinate::Text(text) if self.encrypted != Some(true) => text.trim().parse().ok(),
+            Coordinate::Text(_) => None,
+        };
+        Some((degrees(location.latitude.as_ref()?)?, degrees(location.longitude.as_ref()?)?))
+    }
+    /// Remembers the (possibly encrypted) fields as they were received in the `original_*` fields
+    /// of the message and its files.
+    pub fn keep_originals(&mut self) {
+        self.original_text = self.text.clone();
+        self.original_location = self.location.clone();
+        self.original_metainfo = self.metainfo.clone();
+        self.original_payload = self.messagePayload.clone();
+        for file in self.files.iter_mut().flatten() {
+            file.original_name = Some(file.name.clone());
+        }
+    }
+    /// Puts the values kept by [`Message::keep_originals`] back, undoing [`Message::decrypt`].
+    pub fn restore_originals(&mut self) {
+        if let Some(text) = self.original_text.take() {
+            self.text = Some(text);
+        }
+        if let Some(location) = self.original_location.take() {
+            self.location = Some(location);
+        }
+        if let Some(metainfo) = self.original_metainfo.take() {
+            self.metainfo = Some(metainfo);
+        }
+        if let Some(payload) = self.original_payload.take() {
+            self.messagePayload = Some(payload);
+        }
+        for file in self.files.iter_mut().flatten() {
+            if let Some(name) = file.original_name.take() {
+                file.name = name;
+            }
+        }
+    }
+    /// Decrypts the text, location, metainfo and payload of an encrypted message as well as the names
+    /// of its encrypted files in place using the chat's AES key. The encrypted values are kept in the `original_*` fields.
+    /// Unencrypted and empty fields are left untouched. If any field fails to decrypt, the message isn't changed at all.
+    pub fn decrypt(&mut self, key: &[u8]) -> Result<()> {
+        let mut files = self.files.clone();
+        for file in files.iter_mut().flatten() {
+            file.decrypt(key)?;
+        }
+        if self.encrypted != Some(true) {
+            self.files = files;
+            return Ok(());
+        }
+        let iv = self.iv.as_deref();
+        let text = match &self.text {
+            Some(text) if !text.is_empty() => Some(decrypt_hex(key, iv, text)?),
+            _ => None,
+        };
+        let location = match &self.location {
+            Some(location) => Some(MessageLocation {
+                longitude: decrypt_coordinate(key, iv, location.longitude.as_ref())?,
+                latitude: decrypt_coordinate(key, iv, location.latitude.as_ref())?,
+            }),
+            None => None,
+        };
+        let metainfo = match &self.metainfo {
+            Some(metainfo) if !metainfo.is_empty() => Some(decrypt_hex(key, iv, metainfo)?),
+            _ => None,
+        };
+        let payload = match &self.messagePayload {
+            Some(Value::String(payload)) if !payload.is_empty() => {
+                let payload = decrypt_hex(key, iv, payload)?;
+                // the payload is usually JSON, but plain strings are kept as they are
+                Some(serde_json::from_str(&payload).unwrap_or(Value::String(payload)))
+            },
+            _ => None,
+        };
+
+        self.files = files;
+        if let Some(text) = text {
+            self.original_text.get_or_insert_with(|| self.text.clone().unwrap_or_default());
+            self.text = Some(text);
+        }
+        if let Some(location) = location {
+            if self.original_location.is_none() {
+                self.original_location = self.location.clone();
+            }
+            self.location = Some(location);
+        }
+        if let Some(metainfo) = metainfo {
+            self.original_metainfo.get_or_insert_with(|| self.metainfo.clone().unwrap_or_default());
+            self.metainfo = Some(metainfo);
+        }
+        if let Some(payload) = payload {
+            self.original_payload.get_or_insert_with(|| self.messagePayload.clone().unwrap_or_default());
+            self.messagePayload = Some(payload);
+        }
+        Ok(())
+    }
+}
+/// Decrypts a hex encoded AES-256-CBC ciphertext with a hex encoded IV into a string.
+pub(crate) fn decrypt_hex(key: &[u8], iv: Option<&str>, data: &str) -> Result<String> {
+    let encrypted = hex::decode(data)?;
+    let iv = match iv {
+        Some(iv) => Some(hex::decode(iv)?),
+        None => None,
+    };
+    let decrypted = crypto::symmetric_decrypt(EncryptionFunction::Aes256Cbc, key, iv.as_deref(), &encrypted)?;
+    Ok(String::from_utf8(decrypted)?)
+}
+/// Encrypts `data` with AES-256-CBC, hex encoded like incoming message texts.
+pub(crate) fn encrypt_hex(key: &[u8], iv: &[u8], data: &str) -> Result<String> {
+    Ok(hex::encode(crypto::symmetric_encrypt(EncryptionFunction::Aes256Cbc, key, Some(iv), data.as_bytes())?))
+}
+/// A random IV for [`encrypt_hex`].
+pub(crate) fn random_iv() -> Result<Vec<u8>> {
+    let mut iv = vec![0; 16];
+    crypto::random_bytes(&mut iv)?;
+    Ok(iv)
+}
+/// Decrypts a coordinate if it is a non-empty (hex encoded) string, other values are returned as they are.
+fn decrypt_coordinate(key: &[u8], iv: Option<&str>, coordinate: Option<&Coordinate>) -> Result<Option<Coordinate>> {
+    Ok(match coordinate {
+        Some(Coordinate::Text(data)) if !data.is_empty() => {
+            let decrypted = decrypt_hex(key, iv, data)?;
+            Some(Coordinate::Degrees(decrypted.trim().parse()
+                .map_err(|_| Errors::ValueError(format!("{decrypted:?} isn't a coordinate")))?))
+        },
+        other => other.cloned(),
+    })
 }
 #[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq)]
 #[serde(untagged)]
@@ -53,14 +202,57 @@ pub enum PossibleReply {
 #[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq)]
 #[serde(untagged)]
 pub enum PossibleSender {
-    MessageSender(DetailedPersonInfo),
+    MessageSender(Box<DetailedPersonInfo>),
     String(String),
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq)]
 pub struct MessageLocation {
-    pub longitude: Option<Value>,
-    pub latitude: Option<Value>,
+    pub longitude: Option<Coordinate>,
+    pub latitude: Option<Coordinate>,
+}
+impl MessageLocation {
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        Self {
+            longitude: Some(Coordinate::Degrees(longitude)),
+            latitude: Some(Coordinate::Degrees(latitude)),
+        }
+    }
+}
+/// A latitude or longitude as received: degrees, or a hex encoded ciphertext in encrypted messages
+/// until [`Message::decrypt`] turns it into degrees. Use [`Message::coordinates`] to read both.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum Coordinate {
+    Degrees(f64),
+    Text(String),
+}
+impl Display for Coordinate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Coordinate::Degrees(degrees) => write!(f, "{degrees}"),
+            Coordinate::Text(text) => write!(f, "{text}"),
+        }
+    }
+}
+// compared bitwise, so that messages stay `Eq` and `Hash`
+impl PartialEq for Coordinate {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Coordinate::Degrees(a), Coordinate::Degrees(b)) => a.to_bits() == b.to_bits(),
+            (Coordinate::Text(a), Coordinate::Text(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+impl Eq for Coordinate {}
+impl Hash for Coordinate {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Coordinate::Degrees(degrees) => degrees.to_bits().hash(state),
+            Coordinate::Text(text) => text.hash(state),
+        }
+    }
 }
 
 
@@ -78,6 +270,15 @@ pub struct MessageReplyTo {
     pub message_hash: Option<String>,
     pub message_verification: Option<String>,
 }
+impl From<&Message> for MessageReplyTo {
+    fn from(message: &Message) -> Self {
+        Self {
+            message_id: message.id,
+            message_hash: message.hash.clone(),
+            message_verification: message.verification.clone(),
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq)]
 pub struct File {
@@ -105,9 +306,56 @@ pub struct File {
     pub type_id: Option<String>,
     pub uploaded: Option<String>,
     pub virtual_folder: Option<Value>,
+    /// `name` as it was received, before decryption.
+    pub original_name: Option<String>,
+}
+impl File {
+    /// Decrypts the name of an encrypted file in place using the chat's AES key and the file's `e2e_iv`.
+    /// The encrypted name is kept in `original_name`. Names that can't be decrypted are taken to be plain text
+    /// and kept as they are, so a single odd file doesn't fail a whole page of messages.
+    pub fn decrypt(&mut self, key: &[u8]) -> Result<()> {
+        if !self.encrypted || self.name.is_empty() {
+            return Ok(());
+        }
+        let Ok(name) = decrypt_hex(key, self.e2e_iv.as_deref(), &self.name) else {
+            return Ok(());
+        };
+        self.original_name.get_or_insert_with(|| self.name.clone());
+        self.name = name;
+        Ok(())
+    }
 }
 #[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq)]
 pub struct FileDimensions {
     pub height: Option<String>,
     pub width: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::test_support::file;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    #[test]
+    fn encrypted_file_name_is_decrypted() {
+        let iv = [1; 16];
+        let name = encrypt_hex(&KEY, &iv, "report.pdf").unwrap();
+        let mut file = file("f1", json!({"name": name, "encrypted": true, "e2e_iv": hex::encode(iv)}));
+        file.decrypt(&KEY).unwrap();
+        assert_eq!(file.name, "report.pdf");
+        assert_eq!(file.original_name, Some(name));
+    }
+    #[test]
+    fn plain_file_name_is_kept() {
+        // valid hex, but not an encrypted name
+        for name in ["cafe", "deadbeef", "notes.txt"] {
+            let mut file = file("f1", json!({"name": name, "encrypted": true, "e2e_iv": hex::encode([1; 16])}));
+            file.decrypt(&KEY).unwrap();
+            assert_eq!(file.name, name);
+            assert_eq!(file.original_name, None);
+        }
+    }
+}
@@ -1,3 +1,171 @@
+use std::fmt::{Display, Formatter};
+use serde_json::Value;
+use crate::types::chats::channels::Channel;
+use crate::types::chats::conversations::Conversation;
+use crate::crypto::EncryptionFunction;
+use crate::errors::Errors;
+use crate::types::DetailedPersonInfo;
+use crate::Result;
+
 pub mod channels;
 pub mod conversations;
-pub mod messages;
\ No newline at end of file
+pub mod messages;
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum ChatType {
+    Channel,
+    Conversation,
+}
+impl Display for ChatType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            ChatType::Channel => "channel",
+            ChatType::Conversation => "conversation",
+        })
+    }
+}
+
+/// Common view on [`Channel`]s and [`Conversation`]s.
+pub trait Chat {
+    fn id(&self) -> &str;
+    fn chat_type(&self) -> ChatType;
+    fn encrypted(&self) -> bool;
+    /// The chat's AES key, RSA-encrypted for the current user and base64 encoded.
+    fn key(&self) -> Option<&str>;
+    /// Whether a key has been requested for this chat.
+    fn key_requested(&self) -> bool;
+    /// How many members can't read the chat yet because nobody sent them the key.
+    fn num_members_without_keys(&self) -> u64;
+    /// The members, if they are part of the chat object (conversations). Channel members have to be fetched.
+    fn known_members(&self) -> Option<&[DetailedPersonInfo]>;
+    /// How many messages we haven't read yet.
+    fn unread(&self) -> u64;
+    /// The cipher messages and files of this chat are encrypted with.
+    fn cipher(&self) -> Result<EncryptionFunction> {
+        Ok(EncryptionFunction::default())
+    }
+}
+impl Chat for Channel {
+    fn id(&self) -> &str {
+        &self.id
+    }
+    fn chat_type(&self) -> ChatType {
+        ChatType::Channel
+    }
+    fn encrypted(&self) -> bool {
+        self.encrypted
+    }
+    fn key(&self) -> Option<&str> {
+        self.key.as_deref().filter(|key| !key.is_empty())
+    }
+    fn key_requested(&self) -> bool {
+        self.key_requested.as_ref().is_some_and(is_set)
+    }
+    fn num_members_without_keys(&self) -> u64 {
+        self.num_members_without_keys
+    }
+    fn known_members(&self) -> Option<&[DetailedPersonInfo]> {
+        None
+    }
+    fn unread(&self) -> u64 {
+        self.unread
+    }
+    /// Taken from `crypto_properties.encryption_func`, AES-256-CBC if the channel doesn't specify one.
+    fn cipher(&self) -> Result<EncryptionFunction> {
+        match self.crypto_properties.as_ref().and_then(|properties| properties.get("encryption_func")) {
+            Some(Value::String(cipher)) => cipher.parse(),
+            Some(other) if !other.is_null() => Err(Errors::UnsupportedAlgorithm(format!("encryption function {other}"))),
+            _ => Ok(EncryptionFunction::default()),
+        }
+    }
+}
+impl Chat for Conversation {
+    fn id(&self) -> &str {
+        &self.id
+    }
+    fn chat_type(&self) -> ChatType {
+        ChatType::Conversation
+    }
+    fn encrypted(&self) -> bool {
+        self.encrypted
+    }
+    fn key(&self) -> Option<&str> {
+        self.key.as_deref().filter(|key| !key.is_empty())
+    }
+    fn key_requested(&self) -> bool {
+        is_set(&self.key_requested)
+    }
+    fn num_members_without_keys(&self) -> u64 {
+        self.num_members_without_keys as u64
+    }
+    fn known_members(&self) -> Option<&[DetailedPersonInfo]> {
+        Some(&self.members)
+    }
+    fn unread(&self) -> u64 {
+        self.unread_messages as u64
+    }
+}
+/// Either kind of chat, for lists that mix [`Channel`]s and [`Conversation`]s.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum AnyChat {
+    Channel(Box<Channel>),
+    Conversation(Box<Conversation>),
+}
+impl AnyChat {
+    fn inner(&self) -> &dyn Chat {
+        match self {
+            AnyChat::Channel(channel) => channel.as_ref(),
+            AnyChat::Conversation(conversation) => conversation.as_ref(),
+        }
+    }
+}
+impl Chat for AnyChat {
+    fn id(&self) -> &str {
+        self.inner().id()
+    }
+    fn chat_type(&self) -> ChatType {
+        self.inner().chat_type()
+    }
+    fn encrypted(&self) -> bool {
+        self.inner().encrypted()
+    }
+    fn key(&self) -> Option<&str> {
+        self.inner().key()
+    }
+    fn key_requested(&self) -> bool {
+        self.inner().key_requested()
+    }
+    fn num_members_without_keys(&self) -> u64 {
+        self.inner().num_members_without_keys()
+    }
+    fn known_members(&self) -> Option<&[DetailedPersonInfo]> {
+        self.inner().known_members()
+    }
+    fn unread(&self) -> u64 {
+        self.inner().unread()
+    }
+    fn cipher(&self) -> Result<EncryptionFunction> {
+        self.inner().cipher()
+    }
+}
+impl From<Channel> for AnyChat {
+    fn from(channel: Channel) -> Self {
+        AnyChat::Channel(Box::new(channel))
+    }
+}
+impl From<Conversation> for AnyChat {
+    fn from(conversation: Conversation) -> Self {
+        AnyChat::Conversation(Box::new(conversation))
+    }
+}
+/// the API uses `null`, `false`, `0`, `"0"` and `""` for flags that aren't set.
+fn is_set(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty() && s != "0",
+        Value::Array(a) => !a.is_empty(),
+        Value::Object(o) => !o.is_empty(),
+    }
+}
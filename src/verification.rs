@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use crate::crypto::PublicKey;
+use crate::request_types::about::RSAPublicKey;
+use crate::errors::Errors;
+use crate::types::chats::messages::{Message, PossibleSender};
+use crate::types::user::others::User;
+use crate::types::DetailedPersonInfo;
+use crate::Result;
+
+/// Outcome of checking a message's `hash` and `verification` against its sender's signing key.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum VerificationResult {
+    /// The signature matches the message content.
+    Valid,
+    /// The hash or signature doesn't match the message content.
+    Invalid,
+    /// The message doesn't carry a signature.
+    Unsigned,
+    /// The sender is unknown or hasn't published a (usable) signing key.
+    SenderKeyUnavailable,
+    /// The sender's keys didn't pass the [`TrustPolicy`], so its signing key wasn't used.
+    UntrustedSenderKey(KeyTrust),
+}
+
+/// The content of a message as it is sent, and the part of it that is covered by its hash and signature.
+///
+/// Only the text is hashed and signed (see [`SignedContent::to_bytes`]), like the crate's original verification code
+/// did; files and location are carried along for sending. Whether the official clients sign exactly these bytes
+/// hasn't been confirmed with a real message yet, which is why verifying and signing need the `experimental` feature.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct SignedContent {
+    /// Hex encoded ciphertext for encrypted messages.
+    pub text: String,
+    /// Hex encoded IV, only set for encrypted messages.
+    pub iv: Option<String>,
+    pub files: Vec<String>,
+    pub latitude: Option<String>,
+    pub longitude: Option<String>,
+}
+impl SignedContent {
+    /// The bytes that get hashed and signed: the raw ciphertext (the hex decoded text) for encrypted messages,
+    /// the UTF-8 text otherwise.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match &self.iv {
+            Some(_) => hex::decode(&self.text).unwrap_or_else(|_| self.text.clone().into_bytes()),
+            None => self.text.clone().into_bytes(),
+        }
+    }
+    /// SHA-256 of [`SignedContent::to_bytes`].
+    pub fn digest(&self) -> Result<Vec<u8>> {
+        crate::crypto::sha256(&self.to_bytes())
+    }
+    /// [`SignedContent::digest`], base64 encoded like [`Message::hash`].
+    pub fn hash(&self) -> Result<String> {
+        Ok(BASE64.encode(self.digest()?))
+    }
+}
+impl From<&Message> for SignedContent {
+    fn from(message: &Message) -> Self {
+        let location = message.original_location.as_ref().or(message.location.as_ref());
+        Self {
+            // `original_text` holds the text as it was received, before decryption.
+            text: message.original_text.clone().or(message.text.clone()).unwrap_or_default(),
+            iv: message.iv.clone().filter(|_| message.encrypted == Some(true)),
+            files: message.files.iter().flatten().map(|file| file.id.clone()).collect(),
+            latitude: location.and_then(|l| l.latitude.as_ref()).map(|latitude| latitude.to_string()),
+            longitude: location.and_then(|l| l.longitude.as_ref()).map(|longitude| longitude.to_string()),
+        }
+    }
+}
+
+/// Caches the public signing keys of message senders by user id,
+/// so that verifying a page of messages doesn't fetch the same user over and over.
+#[derive(Debug, Default)]
+pub struct SenderKeyCache {
+    keys: Mutex<HashMap<String, PublicKey>>,
+}
+impl SenderKeyCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn get(&self, user_id: &str) -> Option<PublicKey> {
+        self.keys.lock().unwrap().get(user_id).cloned()
+    }
+    pub fn insert(&self, user_id: impl ToString, key: PublicKey) {
+        self.keys.lock().unwrap().insert(user_id.to_string(), key);
+    }
+    pub fn clear(&self) {
+        self.keys.lock().unwrap().clear();
+    }
+}
+
+/// Returns the sender's user id, or `None` if the sender isn't known.
+pub(crate) fn sender_id(message: &Message) -> Option<String> {
+    match &message.sender {
+        PossibleSender::MessageSender(sender) => sender.id.clone(),
+        PossibleSender::String(_) => None,
+    }
+}
+/// Parses a JWK encoded public signing key, returning `None` for missing or malformed keys.
+pub(crate) fn parse_signing_key(jwk: &str) -> Option<PublicKey> {
+    if jwk.is_empty() {
+        return None;
+    }
+    jwk.parse::<RSAPublicKey>().ok()?.to_key().ok()
+}
+/// Checks `message` against the signing key of its sender.
+/// A `hash` echoed by the server has to match the content as well. See [`SignedContent`] for what is covered.
+#[cfg(feature = "experimental")]
+pub fn verify_with_key(message: &Message, key: &PublicKey) -> Result<VerificationResult> {
+    let Some(verification) = &message.verification else {
+        return Ok(VerificationResult::Unsigned);
+    };
+    let content = SignedContent::from(message);
+    if let Some(hash) = &message.hash {
+        if BASE64.decode(hash).ok() != Some(content.digest()?) {
+            return Ok(VerificationResult::Invalid);
+        }
+    }
+    let Ok(signature) = hex::decode(verification) else {
+        return Ok(VerificationResult::Invalid);
+    };
+    Ok(if key.verify(&content.to_bytes(), &signature) {
+        VerificationResult::Valid
+    } else {
+        VerificationResult::Invalid
+    })
+}
+
+/// How far a contact's public encryption key can be trusted.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum KeyTrust {
+    /// The key is signed by the company CA and by the contact's own signing key.
+    Trusted,
+    /// The contact hasn't published a key, a signing key or one of the signatures.
+    MissingSignature,
+    /// The company CA signature doesn't match the key.
+    InvalidCaSignature,
+    /// The contact's own signature doesn't match the key.
+    InvalidSelfSignature,
+}
+/// Whether keys that aren't [`KeyTrust::Trusted`] may be used to encrypt for a contact.
+///
+/// [`ContactKeys::verify`] hasn't been confirmed against real CA-signed keys yet, so requiring trusted keys
+/// may refuse every contact. That's why [`TrustPolicy::AllowUntrusted`] is the default for now.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub enum TrustPolicy {
+    RequireTrusted,
+    #[default]
+    AllowUntrusted,
+}
+impl TrustPolicy {
+    pub fn allows(&self, trust: KeyTrust) -> bool {
+        *self == TrustPolicy::AllowUntrusted || trust == KeyTrust::Trusted
+    }
+}
+
+/// The public keys and key signatures of a contact, as found on [`User`] and [`DetailedPersonInfo`].
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct ContactKeys {
+    pub user_id: String,
+    pub public_key: String,
+    pub public_key_signature: Option<String>,
+    pub public_signing_key: Option<String>,
+    pub public_key_ca_signature: Option<String>,
+}
+impl From<&User> for ContactKeys {
+    fn from(user: &User) -> Self {
+        Self {
+            user_id: user.id.clone(),
+            public_key: user.public_key.clone(),
+            public_key_signature: Some(user.public_key_signature.clone()).filter(|s| !s.is_empty()),
+            public_signing_key: Some(user.public_signing_key.clone()).filter(|s| !s.is_empty()),
+            public_key_ca_signature: Some(user.public_key_ca_signature.clone()).filter(|s| !s.is_empty()),
+        }
+    }
+}
+impl From<&DetailedPersonInfo> for ContactKeys {
+    fn from(person: &DetailedPersonInfo) -> Self {
+        Self {
+            user_id: person.id.clone().unwrap_or_default(),
+            public_key: person.public_key.clone().unwrap_or_default(),
+            public_key_signature: person.public_key_signature.clone().filter(|s| !s.is_empty()),
+            public_signing_key: person.public_signing_key.clone().filter(|s| !s.is_empty()),
+            public_key_ca_signature: person.public_key_ca_signature.clone().filter(|s| !s.is_empty()),
+        }
+    }
+}
+impl ContactKeys {
+    /// The contact's public encryption key.
+    pub fn encryption_key(&self) -> Result<PublicKey> {
+        if self.public_key.is_empty() {
+            return Err(Errors::ValueError(format!("user {} has no public key", self.user_id)));
+        }
+        self.public_key.parse::<RSAPublicKey>()?.to_key()
+    }
+    /// Checks that the public key is signed by the company CA (`ca_key`) and by the contact's own signing key.
+    ///
+    /// Both signatures are taken to be RSA PKCS#1 v1.5 SHA-256 signatures over the bytes of `public_key`,
+    /// i.e. the JWK string exactly as the server returns it. This hasn't been confirmed against keys published
+    /// by the official clients; if it doesn't hold, their contacts come out [`KeyTrust::InvalidCaSignature`].
+    pub fn verify(&self, ca_key: &PublicKey) -> Result<KeyTrust> {
+        let (Some(signature), Some(signing_key), Some(ca_signature)) = (&self.public_key_signature, &self.public_signing_key, &self.public_key_ca_signature) else {
+            return Ok(KeyTrust::MissingSignature);
+        };
+        if self.public_key.is_empty() {
+            return Ok(KeyTrust::MissingSignature);
+        }
+        if !verify_key_signature(self.public_key.as_bytes(), ca_signature, ca_key)? {
+            return Ok(KeyTrust::InvalidCaSignature);
+        }
+        let Some(signing_key) = parse_signing_key(signing_key) else {
+            return Ok(KeyTrust::MissingSignature);
+        };
+        if !verify_key_signature(self.public_key.as_bytes(), signature, &signing_key)? {
+            return Ok(KeyTrust::InvalidSelfSignature);
+        }
+        Ok(KeyTrust::Trusted)
+    }
+    /// Returns the public encryption key if `policy` allows using it given the result of [`ContactKeys::verify`].
+    pub fn trusted_encryption_key(&self, ca_key: &PublicKey, policy: TrustPolicy) -> Result<PublicKey> {
+        let trust = self.verify(ca_key)?;
+        if !policy.allows(trust) {
+            return Err(Errors::UntrustedKey(self.user_id.clone(), trust));
+        }
+        self.encryption_key()
+    }
+    /// Returns the public signing key if `policy` allows using it given the result of [`ContactKeys::verify`],
+    /// or `None` if the contact hasn't published a usable signing key.
+    pub fn trusted_signing_key(&self, ca_key: &PublicKey, policy: TrustPolicy) -> Result<Option<PublicKey>> {
+        let Some(signing_key) = self.public_signing_key.as_deref().and_then(parse_signing_key) else {
+            return Ok(None);
+        };
+        let trust = self.verify(ca_key)?;
+        if !policy.allows(trust) {
+            return Err(Errors::UntrustedKey(self.user_id.clone(), trust));
+        }
+        Ok(Some(signing_key))
+    }
+}
+/// Key signatures are expected hex encoded like message signatures. Signatures that aren't valid hex
+/// are decoded as base64 instead, as the encoding used by the official clients isn't known.
+fn verify_key_signature(data: &[u8], signature: &str, key: &PublicKey) -> Result<bool> {
+    let Some(signature) = hex::decode(signature).ok().or_else(|| BASE64.decode(signature).ok()) else {
+        return Ok(false);
+    };
+    Ok(key.verify(data, &signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::crypto::{self, PrivateKey};
+    use crate::test_support::{message, private_key, CA_KEY, ENCRYPTION_KEY, SIGNING_KEY};
+
+    /// `b"hello"` signed with `testdata/signing_key.pem` by `openssl dgst -sha256 -sign`.
+    #[cfg(feature = "experimental")]
+    const SIGNATURE: &str = "21f86f7c3c4a401db8be348f83cfe9a3aaf7c14500b1d1a73583e6122dddd9752e61121a411f3716bcc889b90d16ad712c79d2b5e4f0720a57209509a37f1e12d9dfafa989aa49ba6d3183a35406906be653a45ca7a279a236db64c6eb5099d0f6902a6e2978b922121a2c162a1c3cd0c4395e6bcf9432fb662116845dff7afa8527e54e2833918b4b292f28ad5ab874e4b354454b93306293fd2a391d445e03413cd3019857c0eaeda1743eec89abbf16f9c536523cde6d9a5a02737ec0bf18a096ea9350fadb58c2198636799fa6da678cc2602528c7a34439f8aff1a33a9c3c9376a55bd58cf508730cdf69f9cb43b3549f9e526ba3f3fe14e4c669f9bf9c";
+
+    /// An encrypted message whose ciphertext happens to be `b"hello"`.
+    fn encrypted_content() -> SignedContent {
+        SignedContent { text: hex::encode("hello"), iv: Some("00".repeat(16)), ..SignedContent::default() }
+    }
+
+    #[test]
+    fn signed_bytes_are_the_raw_text() {
+        assert_eq!(encrypted_content().to_bytes(), b"hello");
+        let plain = SignedContent { text: "hello".to_string(), files: vec!["f1".to_string()], ..SignedContent::default() };
+        assert_eq!(plain.to_bytes(), b"hello");
+        assert_eq!(plain.hash().unwrap(), "LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=");
+    }
+    #[test]
+    fn iv_of_unencrypted_message_is_ignored() {
+        let message = message("42", json!({"text": "hello", "iv": "00".repeat(16), "encrypted": false}));
+        assert_eq!(SignedContent::from(&message).to_bytes(), b"hello");
+    }
+    /// Only shows that signatures use the same scheme as `openssl dgst -sha256 -sign`,
+    /// not that the signed bytes match the official clients.
+    #[cfg(feature = "experimental")]
+    #[test]
+    fn openssl_signature_over_signed_bytes_verifies() {
+        let content = encrypted_content();
+        let message = message("42", json!({
+            "text": content.text,
+            "iv": content.iv,
+            "encrypted": true,
+            "hash": content.hash().unwrap(),
+            "verification": SIGNATURE,
+        }));
+        let key = private_key(SIGNING_KEY).public_key().unwrap();
+        assert_eq!(verify_with_key(&message, &key).unwrap(), VerificationResult::Valid);
+    }
+    #[test]
+    fn unsigned_signing_key_is_untrusted() {
+        let key = private_key(SIGNING_KEY).public_key().unwrap();
+        let ca_key = private_key(CA_KEY).public_key().unwrap();
+        let contact = ContactKeys {
+            user_id: "42".to_string(),
+            public_signing_key: Some(RSAPublicKey::from_key(&key).to_jwk().unwrap()),
+            ..ContactKeys::default()
+        };
+        assert!(matches!(contact.trusted_signing_key(&ca_key, TrustPolicy::RequireTrusted),
+                         Err(Errors::UntrustedKey(_, KeyTrust::MissingSignature))));
+        assert_eq!(contact.trusted_signing_key(&ca_key, TrustPolicy::AllowUntrusted).unwrap(), Some(key));
+        assert_eq!(ContactKeys::default().trusted_signing_key(&ca_key, TrustPolicy::RequireTrusted).unwrap(), None);
+    }
+
+    /// A contact whose encryption key JWK is signed by `ca` and by its own signing key.
+    fn signed_contact(ca: &PrivateKey, signing: &PrivateKey) -> ContactKeys {
+        let public_key = RSAPublicKey::from_key(&private_key(ENCRYPTION_KEY).public_key().unwrap()).to_jwk().unwrap();
+        ContactKeys {
+            user_id: "42".to_string(),
+            public_key_signature: Some(hex::encode(crypto::rsa_sign(signing, public_key.as_bytes()).unwrap())),
+            public_key_ca_signature: Some(hex::encode(crypto::rsa_sign(ca, public_key.as_bytes()).unwrap())),
+            public_signing_key: Some(RSAPublicKey::from_key(&signing.public_key().unwrap()).to_jwk().unwrap()),
+            public_key,
+        }
+    }
+    #[test]
+    fn signed_contact_is_trusted() {
+        let ca = private_key(CA_KEY);
+        let contact = signed_contact(&ca, &private_key(SIGNING_KEY));
+        assert_eq!(contact.verify(&ca.public_key().unwrap()).unwrap(), KeyTrust::Trusted);
+        // base64 signatures are accepted as well
+        let base64_contact = ContactKeys {
+            public_key_signature: contact.public_key_signature.as_deref().map(|s| BASE64.encode(hex::decode(s).unwrap())),
+            public_key_ca_signature: contact.public_key_ca_signature.as_deref().map(|s| BASE64.encode(hex::decode(s).unwrap())),
+            ..contact.clone()
+        };
+        assert_eq!(base64_contact.verify(&ca.public_key().unwrap()).unwrap(), KeyTrust::Trusted);
+    }
+    #[test]
+    fn wrong_signatures_are_detected() {
+        let ca = private_key(CA_KEY);
+        let signing = private_key(SIGNING_KEY);
+        // signed by another CA
+        let contact = signed_contact(&signing, &signing);
+        assert_eq!(contact.verify(&ca.public_key().unwrap()).unwrap(), KeyTrust::InvalidCaSignature);
+        // self signature made with a key other than the published signing key
+        let contact = ContactKeys {
+            public_key_signature: signed_contact(&ca, &ca).public_key_signature,
+            ..signed_contact(&ca, &signing)
+        };
+        assert_eq!(contact.verify(&ca.public_key().unwrap()).unwrap(), KeyTrust::InvalidSelfSignature);
+        // the key a signature was made over is replaced
+        let contact = ContactKeys {
+            public_key: RSAPublicKey::from_key(&signing.public_key().unwrap()).to_jwk().unwrap(),
+            ..signed_contact(&ca, &signing)
+        };
+        assert_eq!(contact.verify(&ca.public_key().unwrap()).unwrap(), KeyTrust::InvalidCaSignature);
+        assert!(matches!(contact.trusted_encryption_key(&ca.public_key().unwrap(), TrustPolicy::RequireTrusted),
+                         Err(Errors::UntrustedKey(_, KeyTrust::InvalidCaSignature))));
+    }
+}